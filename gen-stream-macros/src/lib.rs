@@ -0,0 +1,517 @@
+//! Procedural macros for [gen-stream](https://docs.rs/gen-stream).
+//!
+//! Use them through the re-exports in `gen_stream` rather than depending on this crate directly.
+
+extern crate proc_macro;
+
+use {
+    proc_macro::TokenStream,
+    proc_macro2::{Group, Spacing, TokenStream as TokenStream2, TokenTree},
+    quote::{quote, quote_spanned},
+    syn::{
+        parse::Parser, parse_macro_input, punctuated::Punctuated, spanned::Spanned, token::Brace,
+        visit_mut::VisitMut, Block, Expr, ExprYield, FnArg, GenericParam, Item, ItemFn, Lifetime,
+        LifetimeParam, Macro, ParenthesizedGenericArguments, Receiver, ReturnType, Token, Type,
+        TypeBareFn, TypeReference,
+    },
+};
+
+/// Rewrites `yield x` into `yield Poll::Ready(x)`, leaving nested closures, `async` blocks and items alone.
+struct YieldReady;
+
+/// Rewrites the yields in the arguments of a macro invocation.
+///
+/// Arguments that parse as statements or as comma-separated expressions are rewritten like the function body.
+/// Other arguments are scanned for `yield`, taking the yielded value to extend up to the next `;` or `,` outside
+/// of brackets and turbofish generics. Bracketed groups are handled the same way recursively, so a block among
+/// such arguments is parsed again.
+fn yield_ready_tokens(tokens: TokenStream2) -> TokenStream2 {
+    if let Ok(mut stmts) = Block::parse_within.parse2(tokens.clone()) {
+        for stmt in &mut stmts {
+            YieldReady.visit_stmt_mut(stmt);
+        }
+        return quote!(#(#stmts)*);
+    }
+    if let Ok(mut exprs) = Punctuated::<Expr, Token![,]>::parse_terminated.parse2(tokens.clone()) {
+        for expr in &mut exprs {
+            YieldReady.visit_expr_mut(expr);
+        }
+        return quote!(#exprs);
+    }
+
+    let mut out = TokenStream2::new();
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(ident) if ident == "yield" => {
+                let mut value = TokenStream2::new();
+                let (mut generics, mut last, mut path_sep) = (0usize, None, false);
+                while let Some(token) = tokens.peek() {
+                    let punct = match token {
+                        TokenTree::Punct(p) => Some((p.as_char(), p.spacing())),
+                        _ => None,
+                    };
+                    match punct {
+                        Some((';', _)) | Some((',', _)) if generics == 0 => break,
+                        Some(('<', _)) if path_sep || generics > 0 => generics += 1,
+                        // `->` and `=>` do not close generics.
+                        Some(('>', _))
+                            if generics > 0
+                                && last != Some(('-', Spacing::Joint))
+                                && last != Some(('=', Spacing::Joint)) =>
+                        {
+                            generics -= 1
+                        }
+                        _ => {}
+                    }
+                    path_sep =
+                        punct.map(|(c, _)| c) == Some(':') && last == Some((':', Spacing::Joint));
+                    last = punct;
+                    value.extend(tokens.next());
+                }
+                let value = yield_ready_tokens(value);
+
+                out.extend(if value.is_empty() {
+                    quote_spanned!(ident.span()=> #ident ::core::task::Poll::Ready(()))
+                } else {
+                    quote_spanned!(ident.span()=> #ident ::core::task::Poll::Ready(#value))
+                });
+            }
+            TokenTree::Group(group) => {
+                let mut rewritten =
+                    Group::new(group.delimiter(), yield_ready_tokens(group.stream()));
+                rewritten.set_span(group.span());
+                out.extend(Some(TokenTree::Group(rewritten)));
+            }
+            token => out.extend(Some(token)),
+        }
+    }
+
+    out
+}
+
+impl VisitMut for YieldReady {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Closure(_) | Expr::Async(_) => {}
+            Expr::Yield(ExprYield {
+                expr: Some(inner), ..
+            }) => {
+                self.visit_expr_mut(inner);
+                let value = &**inner;
+                **inner =
+                    syn::parse_quote_spanned!(value.span()=> ::core::task::Poll::Ready(#value));
+            }
+            Expr::Yield(ExprYield { expr: yielded, .. }) => {
+                *yielded = Some(Box::new(syn::parse_quote!(::core::task::Poll::Ready(()))));
+            }
+            _ => syn::visit_mut::visit_expr_mut(self, expr),
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut Item) {}
+
+    fn visit_macro_mut(&mut self, mac: &mut Macro) {
+        mac.tokens = yield_ready_tokens(mac.tokens.clone());
+    }
+}
+
+/// Names the lifetimes elided in the arguments, so that the generated return type can capture them.
+#[derive(Default)]
+struct NameElided {
+    lifetimes: Vec<Lifetime>,
+}
+
+impl NameElided {
+    fn fresh(&mut self, span: proc_macro2::Span) -> Lifetime {
+        let lifetime = Lifetime::new(&format!("'__gen_stream_{}", self.lifetimes.len()), span);
+        self.lifetimes.push(lifetime.clone());
+        lifetime
+    }
+}
+
+impl VisitMut for NameElided {
+    fn visit_receiver_mut(&mut self, receiver: &mut Receiver) {
+        if receiver.colon_token.is_some() {
+            return self.visit_type_mut(&mut receiver.ty);
+        }
+        if let Some((and, lifetime @ None)) = &mut receiver.reference {
+            let fresh = self.fresh(and.span);
+            if let Type::Reference(ty) = &mut *receiver.ty {
+                ty.lifetime = Some(fresh.clone());
+            }
+            *lifetime = Some(fresh);
+        }
+    }
+
+    fn visit_type_reference_mut(&mut self, ty: &mut TypeReference) {
+        if ty.lifetime.is_none() {
+            ty.lifetime = Some(self.fresh(ty.and_token.span));
+        }
+        syn::visit_mut::visit_type_reference_mut(self, ty);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.fresh(lifetime.span());
+        }
+    }
+
+    // Lifetimes elided in function pointers and `Fn` bounds are higher-ranked.
+    fn visit_type_bare_fn_mut(&mut self, _: &mut TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(&mut self, _: &mut ParenthesizedGenericArguments) {
+    }
+}
+
+enum Kind {
+    Plain,
+    Perpetual,
+    Try,
+}
+
+#[derive(Default)]
+struct Args {
+    item: Option<Type>,
+    ok: Option<Type>,
+    error: Option<Type>,
+}
+
+impl Args {
+    fn parse(kind: &Kind, attr: TokenStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+
+        let parser = syn::meta::parser(|meta| {
+            let slot = match kind {
+                Kind::Plain | Kind::Perpetual if meta.path.is_ident("item") => &mut args.item,
+                Kind::Try if meta.path.is_ident("ok") => &mut args.ok,
+                Kind::Try if meta.path.is_ident("error") => &mut args.error,
+                _ => return Err(meta.error("unsupported argument")),
+            };
+            *slot = Some(meta.value()?.parse()?);
+            Ok(())
+        });
+        parser.parse(attr)?;
+
+        Ok(args)
+    }
+}
+
+fn missing(name: &str) -> syn::Error {
+    syn::Error::new(
+        proc_macro2::Span::call_site(),
+        format!("missing `{} = ...` argument", name),
+    )
+}
+
+/// Builds the `static move` generator expression for the given body.
+fn generator(kind: &Kind, error: Option<&Type>, mut body: Block) -> TokenStream2 {
+    YieldReady.visit_block_mut(&mut body);
+
+    match kind {
+        Kind::Plain => quote! {
+            #[coroutine] static move || #body
+        },
+        Kind::Perpetual => quote! {
+            #[coroutine] static move || -> ::core::convert::Infallible #body
+        },
+        Kind::Try => {
+            let error = error.map_or_else(|| quote!(_), |e| quote!(#e));
+            quote! {
+                #[coroutine] static move || -> ::core::result::Result<(), #error> {
+                    let () = #body;
+                    #[allow(unreachable_code)]
+                    ::core::result::Result::Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn expand(kind: Kind, attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Args::parse(&kind, attr) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let ItemFn {
+        attrs,
+        vis,
+        mut sig,
+        block,
+    } = parse_macro_input!(item as ItemFn);
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        return quote_spanned!(ty.span()=> compile_error!("generator stream functions must not declare a return type");)
+            .into();
+    }
+
+    let (wrapper, item_ty) = match kind {
+        Kind::Plain | Kind::Perpetual => match &args.item {
+            Some(item) => (
+                if let Kind::Plain = kind {
+                    quote!(GenStream)
+                } else {
+                    quote!(GenPerpetualStream)
+                },
+                quote!(#item),
+            ),
+            None => return missing("item").to_compile_error().into(),
+        },
+        Kind::Try => match (&args.ok, &args.error) {
+            (Some(ok), Some(error)) => (
+                quote!(GenTryStream),
+                quote!(::core::result::Result<#ok, #error>),
+            ),
+            (None, _) => return missing("ok").to_compile_error().into(),
+            (_, None) => return missing("error").to_compile_error().into(),
+        },
+    };
+
+    let mut elided = NameElided::default();
+    for input in &mut sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => elided.visit_receiver_mut(receiver),
+            FnArg::Typed(arg) => elided.visit_type_mut(&mut arg.ty),
+        }
+    }
+    let at = sig.generics.lifetimes().count();
+    for (i, lifetime) in elided.lifetimes.into_iter().enumerate() {
+        sig.generics
+            .params
+            .insert(at + i, GenericParam::Lifetime(LifetimeParam::new(lifetime)));
+    }
+
+    // The stream holds on to the arguments, so it captures all their lifetimes. Type parameters are
+    // captured anyway.
+    let captures = sig.generics.lifetimes().map(|param| &param.lifetime);
+    let generator = generator(&kind, args.error.as_ref(), *block);
+    sig.output = syn::parse_quote!(
+        -> impl ::gen_stream::__private::Stream<Item = #item_ty>
+            #(+ ::gen_stream::__private::Captures<#captures>)*
+    );
+
+    quote!(
+        #(#attrs)*
+        #vis #sig {
+            ::gen_stream::#wrapper::from(::gen_stream::__private::Box::pin(#generator))
+        }
+    )
+    .into()
+}
+
+/// Turns a function with `yield` statements into one returning `impl Stream<Item = T>` backed by `GenStream`.
+///
+/// Takes the item type as `item = T`. Every `yield x` becomes `yield Poll::Ready(x)`, also inside the arguments
+/// of macros.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*},
+///     gen_stream::gen_stream,
+/// };
+///
+/// #[gen_stream(item = u32)]
+/// fn doubled(input: Vec<u32>) {
+///     for v in input {
+///         yield v * 2;
+///     }
+/// }
+///
+/// assert_eq!(block_on(doubled(vec![1, 2]).collect::<Vec<_>>()), vec![2, 4]);
+/// ```
+///
+/// The returned stream may borrow from the arguments, including `self`:
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*},
+///     gen_stream::gen_stream,
+///     std::collections::HashMap,
+/// };
+///
+/// macro_rules! each {
+///     ($v:pat in $e:expr => $body:expr) => {
+///         for $v in $e {
+///             $body;
+///         }
+///     };
+/// }
+///
+/// #[gen_stream(item = char)]
+/// fn vowels(s: &str) {
+///     for c in s.chars().filter(|c| "aeiou".contains(*c)) {
+///         yield c;
+///     }
+/// }
+///
+/// struct Tally(Vec<u32>);
+///
+/// impl Tally {
+///     #[gen_stream(item = HashMap<u32, u32>)]
+///     fn counts(&self, step: &u32) {
+///         each!(v in &self.0 => yield HashMap::<u32, u32>::from([(*v, v * step)]));
+///     }
+/// }
+///
+/// assert_eq!(block_on(vowels("generator").collect::<String>()), "eeao");
+///
+/// let tally = Tally(vec![1, 2]);
+/// let counts = block_on(tally.counts(&10).collect::<Vec<_>>());
+/// assert_eq!(counts, vec![HashMap::from([(1, 10)]), HashMap::from([(2, 20)])]);
+/// ```
+///
+/// The item type is mandatory:
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+///
+/// #[gen_stream::gen_stream]
+/// fn unit() {
+///     yield ();
+/// }
+/// ```
+///
+/// The return type is generated, so the function must not declare one:
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+///
+/// #[gen_stream::gen_stream(item = u32)]
+/// fn one() -> u32 {
+///     yield 1;
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gen_stream(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Kind::Plain, attr, item)
+}
+
+/// Like `#[gen_stream]` but backed by `GenPerpetualStream`. The function body must never complete.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*},
+///     gen_stream::gen_perpetual_stream,
+/// };
+///
+/// #[gen_perpetual_stream(item = u64)]
+/// fn powers_of_two() {
+///     let mut v = 1;
+///     loop {
+///         yield v;
+///         v *= 2;
+///     }
+/// }
+///
+/// assert_eq!(block_on(powers_of_two().take(4).collect::<Vec<_>>()), vec![1, 2, 4, 8]);
+/// ```
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+///
+/// #[gen_stream::gen_perpetual_stream]
+/// fn ones() {
+///     loop {
+///         yield 1u32;
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gen_perpetual_stream(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Kind::Perpetual, attr, item)
+}
+
+/// Like `#[gen_stream]` but backed by `GenTryStream`, producing `impl Stream<Item = Result<T, E>>`.
+///
+/// Takes the item types as `ok = T, error = E`. The body may use `?` or `return Err(e)` to end the stream with an error.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*},
+///     gen_stream::gen_try_stream,
+/// };
+///
+/// #[gen_try_stream(ok = u8, error = String)]
+/// fn checked(input: Vec<u32>) {
+///     for v in input {
+///         if v > 255 {
+///             return Err(format!("{} out of range", v));
+///         }
+///         yield v as u8;
+///     }
+/// }
+///
+/// assert_eq!(
+///     block_on(checked(vec![1, 256, 2]).collect::<Vec<_>>()),
+///     vec![Ok(1), Err("256 out of range".to_string())],
+/// );
+/// ```
+///
+/// Both `ok` and `error` are mandatory:
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+///
+/// #[gen_stream::gen_try_stream(error = String)]
+/// fn missing_ok() {
+///     yield 1u8;
+/// }
+/// ```
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+///
+/// #[gen_stream::gen_try_stream(ok = u8)]
+/// fn missing_error() {
+///     yield 1u8;
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn gen_try_stream(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(Kind::Try, attr, item)
+}
+
+fn expand_block(kind: Kind, input: TokenStream) -> TokenStream {
+    let stmts = match Block::parse_within.parse(input) {
+        Ok(stmts) => stmts,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let wrapper = match kind {
+        Kind::Try => quote!(GenTryStream),
+        _ => quote!(GenStream),
+    };
+    let generator = generator(
+        &kind,
+        None,
+        Block {
+            brace_token: Brace::default(),
+            stmts,
+        },
+    );
+
+    quote!(
+        ::gen_stream::#wrapper::from(::gen_stream::__private::Box::pin(#generator))
+    )
+    .into()
+}
+
+/// Builds a `GenStream` from a block with `yield` statements.
+#[proc_macro]
+pub fn gen_stream_block(input: TokenStream) -> TokenStream {
+    expand_block(Kind::Plain, input)
+}
+
+/// Builds a `GenTryStream` from a block with `yield` statements, `?` ends the stream with an error.
+#[proc_macro]
+pub fn try_gen_stream_block(input: TokenStream) -> TokenStream {
+    expand_block(Kind::Try, input)
+}
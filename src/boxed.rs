@@ -0,0 +1,113 @@
+use {
+    crate::{GenPerpetualStream, GenStream, GenTryStream, Resume},
+    alloc::boxed::Box,
+    core::{convert::Infallible, pin::Pin, task::Poll},
+    futures_core::Stream,
+};
+
+/// Boxed `GenStream` with the generator type erased.
+pub type BoxGenStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + 'a>>;
+
+/// Boxed `GenStream` with the generator type erased, without the `Send` bound.
+pub type LocalBoxGenStream<'a, T> = Pin<Box<dyn Stream<Item = T> + 'a>>;
+
+/// Boxed `GenPerpetualStream` with the generator type erased.
+pub type BoxGenPerpetualStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + 'a>>;
+
+/// Boxed `GenPerpetualStream` with the generator type erased, without the `Send` bound.
+pub type LocalBoxGenPerpetualStream<'a, T> = Pin<Box<dyn Stream<Item = T> + 'a>>;
+
+/// Boxed `GenTryStream` with the generator type erased.
+pub type BoxGenTryStream<'a, T, E> = Pin<Box<dyn Stream<Item = Result<T, E>> + Send + 'a>>;
+
+/// Boxed `GenTryStream` with the generator type erased, without the `Send` bound.
+pub type LocalBoxGenTryStream<'a, T, E> = Pin<Box<dyn Stream<Item = Result<T, E>> + 'a>>;
+
+impl<G> GenStream<G> {
+    /// Creates a pinned, boxed stream that can be stored without naming the generator type.
+    ///
+    /// The whole stream is pinned on the heap, so `static` generators need no `Box::pin` of their own.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{BoxGenStream, GenStream},
+    /// };
+    ///
+    /// struct Feed {
+    ///     items: BoxGenStream<'static, u32>,
+    /// }
+    ///
+    /// let mut feed = Feed {
+    ///     items: GenStream::boxed(#[coroutine] static move || {
+    ///         let v = 1;
+    ///         let r = &v;
+    ///         yield Poll::Ready(*r);
+    ///         yield Poll::Ready(*r + 1);
+    ///     }),
+    /// };
+    ///
+    /// assert_eq!(block_on(feed.items.by_ref().collect::<Vec<_>>()), vec![1, 2]);
+    /// ```
+    pub fn boxed<'a, T>(inner: G) -> BoxGenStream<'a, T>
+    where
+        G: Resume<Yield = Poll<T>, Return = ()> + Send + 'a,
+    {
+        Box::pin(Self::from(inner))
+    }
+
+    /// Creates a pinned, boxed stream that can be stored without naming the generator type.
+    ///
+    /// Same as [`boxed`](#method.boxed), for generators that are not `Send`.
+    pub fn boxed_local<'a, T>(inner: G) -> LocalBoxGenStream<'a, T>
+    where
+        G: Resume<Yield = Poll<T>, Return = ()> + 'a,
+    {
+        Box::pin(Self::from(inner))
+    }
+}
+
+impl<G> GenPerpetualStream<G> {
+    /// Creates a pinned, boxed never-ending stream that can be stored without naming the generator type.
+    pub fn boxed<'a, T>(inner: G) -> BoxGenPerpetualStream<'a, T>
+    where
+        G: Resume<Yield = Poll<T>> + Send + 'a,
+        G::Return: Into<Infallible>,
+    {
+        Box::pin(Self::from(inner))
+    }
+
+    /// Creates a pinned, boxed never-ending stream that can be stored without naming the generator type.
+    ///
+    /// Same as [`boxed`](#method.boxed), for generators that are not `Send`.
+    pub fn boxed_local<'a, T>(inner: G) -> LocalBoxGenPerpetualStream<'a, T>
+    where
+        G: Resume<Yield = Poll<T>> + 'a,
+        G::Return: Into<Infallible>,
+    {
+        Box::pin(Self::from(inner))
+    }
+}
+
+impl<G> GenTryStream<G> {
+    /// Creates a pinned, boxed fallible stream that can be stored without naming the generator type.
+    pub fn boxed<'a, T, E>(inner: G) -> BoxGenTryStream<'a, T, E>
+    where
+        G: Resume<Yield = Poll<T>, Return = Result<(), E>> + Send + 'a,
+    {
+        Box::pin(Self::from(inner))
+    }
+
+    /// Creates a pinned, boxed fallible stream that can be stored without naming the generator type.
+    ///
+    /// Same as [`boxed`](#method.boxed), for generators that are not `Send`.
+    pub fn boxed_local<'a, T, E>(inner: G) -> LocalBoxGenTryStream<'a, T, E>
+    where
+        G: Resume<Yield = Poll<T>, Return = Result<(), E>> + 'a,
+    {
+        Box::pin(Self::from(inner))
+    }
+}
@@ -0,0 +1,642 @@
+use {
+    crate::{
+        tls::{with_cancellation, Cancellation},
+        GenRecoverableStream, GenState, GenStream, GenStreamWithReturn, GenTryStream, Resume,
+        SizeHint, StreamError,
+    },
+    core::{
+        fmt,
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::{stream::FusedStream, Stream},
+    pin_project_lite::pin_project,
+};
+
+#[cfg(feature = "std")]
+use core::task::Waker;
+
+/// Future cancelling a generator, created by `cancel` on GenStreams.
+///
+/// Resumes the generator with the cancellation signal set, discarding any items it still yields, until it completes
+/// or the given number of resumes is spent. Awaits the generator is waiting on are interrupted.
+/// Resolves to the return value of the generator, or `None` if it had already completed or was given up on.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Cancel<'a, G> {
+    inner: Pin<&'a mut G>,
+    finished: &'a mut bool,
+    hint: &'a mut SizeHint,
+    resumes: usize,
+    cancellation: Cancellation,
+}
+
+impl<'a, G> Cancel<'a, G> {
+    fn new(
+        inner: Pin<&'a mut G>,
+        finished: &'a mut bool,
+        hint: &'a mut SizeHint,
+        resumes: usize,
+    ) -> Self {
+        Self {
+            inner,
+            finished,
+            hint,
+            resumes,
+            cancellation: Cancellation::Interrupt,
+        }
+    }
+
+    /// Terminates the stream, the generator is not resumed again.
+    fn finish<R>(&mut self, res: Option<R>) -> Poll<Option<R>> {
+        *self.finished = true;
+        *self.hint = (0, Some(0));
+
+        Poll::Ready(res)
+    }
+}
+
+impl<G, Y> Future for Cancel<'_, G>
+where
+    G: Resume<Yield = Poll<Y>>,
+{
+    type Output = Option<G::Return>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            if this.resumes == 0 {
+                return this.finish(None);
+            }
+            this.resumes -= 1;
+
+            let inner = this.inner.as_mut();
+            let (state, outcome) = with_cancellation(this.cancellation, || inner.poll_resume(cx));
+            this.cancellation = outcome;
+
+            match state {
+                GenState::Complete(v) => return this.finish(Some(v)),
+                _ if outcome == Cancellation::Abandoned => return this.finish(None),
+                GenState::Yielded(Poll::Ready(_)) => {}
+                GenState::Yielded(Poll::Pending) => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<G, T> GenStream<G>
+where
+    G: Resume<Yield = Poll<T>, Return = ()>,
+{
+    /// Cancels the generator, letting it run the cleanup in its `gen_on_cancel!` block.
+    ///
+    /// The generator is resumed with the cancellation signal set until it completes, which it does on reaching
+    /// a [`gen_on_cancel!`](macro.gen_on_cancel.html) block, but at most `resumes` times. Awaits that would have to
+    /// wait are interrupted as in [`with_cancel`](#method.with_cancel), while awaits in the cleanup run as usual.
+    /// Afterwards the stream is terminated.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     core::pin::Pin,
+    ///     futures::{channel::mpsc, executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, gen_on_cancel, GenStream},
+    /// };
+    ///
+    /// let (mut tx, mut rx) = mpsc::unbounded();
+    ///
+    /// let mut session = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     for i in 0.. {
+    ///         yield Poll::Ready(i);
+    ///         gen_on_cancel! {
+    ///             gen_await!(tx.send("GOODBYE")).unwrap();
+    ///         }
+    ///     }
+    /// }));
+    ///
+    /// block_on(async {
+    ///     assert_eq!(session.next().await, Some(0));
+    ///     assert_eq!(Pin::new(&mut session).cancel(8).await, Some(()));
+    ///     assert_eq!(session.next().await, None);
+    ///     assert_eq!(rx.next().await, Some("GOODBYE"));
+    /// });
+    /// ```
+    ///
+    /// Generators that never reach a `gen_on_cancel!` block are given up on:
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     core::pin::Pin,
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, GenStream},
+    /// };
+    ///
+    /// let mut ones = GenStream::from(Box::pin(#[coroutine] static move || loop {
+    ///     yield Poll::Ready(1);
+    /// }));
+    ///
+    /// let mut idle = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     yield Poll::Ready(1);
+    ///     gen_await!(future::pending::<()>());
+    /// }));
+    ///
+    /// block_on(async {
+    ///     assert_eq!(Pin::new(&mut ones).cancel(8).await, None);
+    ///     assert_eq!(ones.size_hint(), (0, Some(0)));
+    ///     assert_eq!(ones.next().await, None);
+    ///
+    ///     assert_eq!(idle.next().await, Some(1));
+    ///     assert_eq!(Pin::new(&mut idle).cancel(8).await, None);
+    ///     assert_eq!(idle.next().await, None);
+    /// });
+    /// ```
+    pub fn cancel(self: Pin<&mut Self>, resumes: usize) -> Cancel<'_, G> {
+        let this = self.project();
+        Cancel::new(this.inner, this.finished, this.hint, resumes)
+    }
+
+    /// Cancels the generator once `token` resolves, e.g. on shutdown.
+    ///
+    /// From then on [`gen_cancelled!`](macro.gen_cancelled.html) evaluates to `true` in the generator and
+    /// [`gen_on_cancel!`](macro.gen_on_cancel.html) blocks run their cleanup, so the generator does not have to race
+    /// its awaits against the token by hand. A [`gen_await_next!`](macro.gen_await_next.html) or
+    /// [`gen_for!`](macro.gen_for.html) that would have to wait evaluates to `None` instead, as if the stream had ended,
+    /// letting an idle generator leave its loop and reach the cleanup after it. Awaits in `gen_on_cancel!` blocks
+    /// are not interrupted. The output of `token` is ignored.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{channel::{mpsc, oneshot}, executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, gen_await_next, gen_on_cancel, GenStream},
+    /// };
+    ///
+    /// let (shutdown, token) = oneshot::channel::<()>();
+    /// let (tx, mut rx) = mpsc::unbounded();
+    /// let (mut log, mut log_rx) = mpsc::unbounded();
+    ///
+    /// let mut requests = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     while let Some(request) = gen_await_next!(rx) {
+    ///         yield Poll::Ready(request);
+    ///     }
+    ///     gen_on_cancel! {
+    ///         gen_await!(log.send("drained")).unwrap();
+    ///     }
+    /// }))
+    /// .with_cancel(token);
+    ///
+    /// block_on(async {
+    ///     tx.unbounded_send("GET /").unwrap();
+    ///     assert_eq!(requests.next().await, Some("GET /"));
+    ///     shutdown.send(()).unwrap();
+    ///     assert_eq!(requests.next().await, None);
+    ///     assert_eq!(log_rx.next().await, Some("drained"));
+    /// });
+    /// ```
+    ///
+    /// Any other awaiting macro, such as `gen_await!`, cannot produce a value without what it waits for, so when
+    /// it would have to wait, the stream ends right away and the generator is dropped there. Its `gen_on_cancel!`
+    /// blocks are skipped, only destructors run:
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{channel::{mpsc, oneshot}, executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, gen_on_cancel, GenStream},
+    ///     std::{cell::Cell, rc::Rc},
+    /// };
+    ///
+    /// let (shutdown, token) = oneshot::channel::<()>();
+    /// let (tx, mut rx) = mpsc::unbounded();
+    /// let cleaned_up = Rc::new(Cell::new(false));
+    /// let flag = cleaned_up.clone();
+    ///
+    /// let mut requests = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     while let Some(request) = gen_await!(rx.next()) {
+    ///         yield Poll::Ready(request);
+    ///         gen_on_cancel! {
+    ///             flag.set(true);
+    ///         }
+    ///     }
+    /// }))
+    /// .with_cancel(token);
+    ///
+    /// block_on(async {
+    ///     tx.unbounded_send("GET /").unwrap();
+    ///     assert_eq!(requests.next().await, Some("GET /"));
+    ///     assert_eq!(requests.next().now_or_never(), None);
+    ///     shutdown.send(()).unwrap();
+    ///     assert_eq!(requests.next().await, None);
+    /// });
+    /// assert!(!cleaned_up.get());
+    /// ```
+    pub fn with_cancel<C: Future>(self, token: C) -> WithCancel<Self, C> {
+        WithCancel::new(self, token, None)
+    }
+
+    /// Cancels the generator when the stream is dropped before completion.
+    ///
+    /// Since the cleanup cannot be awaited in `drop`, the generator is given at most `resumes` resumes with a no-op
+    /// waker to complete, so only cleanup that does not have to wait is guaranteed to run. Use
+    /// [`cancel`](#method.cancel) to await the cleanup instead.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_on_cancel, GenStream},
+    ///     std::sync::mpsc,
+    /// };
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    ///
+    /// let mut lease = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     loop {
+    ///         yield Poll::Ready("leased");
+    ///         gen_on_cancel! {
+    ///             tx.send("released").unwrap();
+    ///         }
+    ///     }
+    /// }))
+    /// .cancel_on_drop(1);
+    ///
+    /// assert_eq!(block_on(lease.next()), Some("leased"));
+    /// drop(lease);
+    /// assert_eq!(rx.try_recv(), Ok("released"));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn cancel_on_drop(self, resumes: usize) -> CancelOnDrop<Self> {
+        CancelOnDrop::new(self, resumes, |stream, resumes| {
+            let this = stream.project();
+            cancel_blocking(Cancel::new(this.inner, this.finished, this.hint, resumes))
+        })
+    }
+}
+
+impl<G, T, E> GenTryStream<G>
+where
+    G: Resume<Yield = Poll<T>, Return = Result<(), E>>,
+{
+    /// Cancels the generator, letting it run the cleanup in its `gen_on_cancel!` block.
+    ///
+    /// See [`GenStream::cancel`](struct.GenStream.html#method.cancel). Resolves to the result of the cleanup.
+    pub fn cancel(self: Pin<&mut Self>, resumes: usize) -> Cancel<'_, G> {
+        let this = self.project();
+        Cancel::new(this.inner, this.finished, this.hint, resumes)
+    }
+
+    /// Cancels the generator once `token` resolves, ending the stream with a `Cancelled` error.
+    ///
+    /// See [`GenStream::with_cancel`](struct.GenStream.html#method.with_cancel). When the stream ends after the token
+    /// has resolved, `Err(Cancelled.into())` is produced as the last item, also after an error returned by
+    /// the cleanup.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{channel::oneshot, executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_cancelled, Cancelled, GenTryStream},
+    /// };
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Error {
+    ///     Cancelled,
+    /// }
+    ///
+    /// impl From<Cancelled> for Error {
+    ///     fn from(_: Cancelled) -> Self {
+    ///         Error::Cancelled
+    ///     }
+    /// }
+    ///
+    /// let (shutdown, token) = oneshot::channel::<()>();
+    ///
+    /// let mut pages = GenTryStream::from(Box::pin(#[coroutine] static move || {
+    ///     for page in 0.. {
+    ///         if gen_cancelled!() {
+    ///             break;
+    ///         }
+    ///         yield Poll::Ready(page);
+    ///     }
+    ///     Ok::<_, Error>(())
+    /// }))
+    /// .with_cancel(token);
+    ///
+    /// block_on(async {
+    ///     assert_eq!(pages.next().await, Some(Ok(0)));
+    ///     shutdown.send(()).unwrap();
+    ///     assert_eq!(pages.next().await, Some(Err(Error::Cancelled)));
+    ///     assert_eq!(pages.next().await, None);
+    /// });
+    /// ```
+    pub fn with_cancel<C: Future>(self, token: C) -> WithCancel<Self, C>
+    where
+        E: From<Cancelled>,
+    {
+        WithCancel::new(self, token, Some(Err(Cancelled.into())))
+    }
+
+    /// Cancels the generator when the stream is dropped before completion.
+    ///
+    /// See [`GenStream::cancel_on_drop`](struct.GenStream.html#method.cancel_on_drop). An error returned by the cleanup
+    /// is discarded.
+    #[cfg(feature = "std")]
+    pub fn cancel_on_drop(self, resumes: usize) -> CancelOnDrop<Self> {
+        CancelOnDrop::new(self, resumes, |stream, resumes| {
+            let this = stream.project();
+            cancel_blocking(Cancel::new(this.inner, this.finished, this.hint, resumes))
+        })
+    }
+}
+
+impl<G, T, E, F> GenRecoverableStream<G>
+where
+    G: Resume<Yield = Poll<Result<T, E>>, Return = Result<(), F>>,
+{
+    /// Cancels the generator, letting it run the cleanup in its `gen_on_cancel!` block.
+    ///
+    /// See [`GenStream::cancel`](struct.GenStream.html#method.cancel). Resolves to the result of the cleanup.
+    pub fn cancel(self: Pin<&mut Self>, resumes: usize) -> Cancel<'_, G> {
+        let this = self.project();
+        Cancel::new(this.inner, this.finished, this.hint, resumes)
+    }
+
+    /// Cancels the generator once `token` resolves, ending the stream with a fatal `Cancelled` error.
+    ///
+    /// See [`GenStream::with_cancel`](struct.GenStream.html#method.with_cancel). When the stream ends after the token
+    /// has resolved, `Err(StreamError::Fatal(Cancelled.into()))` is produced as the last item.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{channel::oneshot, executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_cancelled, Cancelled, GenRecoverableStream, StreamError},
+    /// };
+    ///
+    /// let (shutdown, token) = oneshot::channel::<()>();
+    ///
+    /// let mut frames = GenRecoverableStream::from(Box::pin(#[coroutine] static move || {
+    ///     while !gen_cancelled!() {
+    ///         yield Poll::Ready(Err::<u32, _>("bad frame"));
+    ///     }
+    ///     Ok::<_, Cancelled>(())
+    /// }))
+    /// .with_cancel(token);
+    ///
+    /// block_on(async {
+    ///     assert_eq!(frames.next().await, Some(Err(StreamError::Recoverable("bad frame"))));
+    ///     shutdown.send(()).unwrap();
+    ///     assert_eq!(frames.next().await, Some(Err(StreamError::Fatal(Cancelled))));
+    ///     assert_eq!(frames.next().await, None);
+    /// });
+    /// ```
+    pub fn with_cancel<C: Future>(self, token: C) -> WithCancel<Self, C>
+    where
+        F: From<Cancelled>,
+    {
+        WithCancel::new(self, token, Some(Err(StreamError::Fatal(Cancelled.into()))))
+    }
+
+    /// Cancels the generator when the stream is dropped before completion.
+    ///
+    /// See [`GenStream::cancel_on_drop`](struct.GenStream.html#method.cancel_on_drop). An error returned by the cleanup
+    /// is discarded.
+    #[cfg(feature = "std")]
+    pub fn cancel_on_drop(self, resumes: usize) -> CancelOnDrop<Self> {
+        CancelOnDrop::new(self, resumes, |stream, resumes| {
+            let this = stream.project();
+            cancel_blocking(Cancel::new(this.inner, this.finished, this.hint, resumes))
+        })
+    }
+}
+
+impl<G, T, R> GenStreamWithReturn<G, R>
+where
+    G: Resume<Yield = Poll<T>, Return = R>,
+{
+    /// Cancels the generator, letting it run the cleanup in its `gen_on_cancel!` block.
+    ///
+    /// See [`GenStream::cancel`](struct.GenStream.html#method.cancel). Resolves to the value the generator returns
+    /// from the cleanup, which is not kept for [`take_return`](#method.take_return).
+    pub fn cancel(self: Pin<&mut Self>, resumes: usize) -> Cancel<'_, G> {
+        let this = self.project();
+        Cancel::new(this.inner, this.finished, this.hint, resumes)
+    }
+
+    /// Cancels the generator once `token` resolves.
+    ///
+    /// See [`GenStream::with_cancel`](struct.GenStream.html#method.with_cancel). The value returned from the cleanup
+    /// is kept as usual and available from [`get_pin_mut`](struct.WithCancel.html#method.get_pin_mut).
+    pub fn with_cancel<C: Future>(self, token: C) -> WithCancel<Self, C> {
+        WithCancel::new(self, token, None)
+    }
+
+    /// Cancels the generator when the stream is dropped before completion.
+    ///
+    /// See [`GenStream::cancel_on_drop`](struct.GenStream.html#method.cancel_on_drop).
+    #[cfg(feature = "std")]
+    pub fn cancel_on_drop(self, resumes: usize) -> CancelOnDrop<Self> {
+        CancelOnDrop::new(self, resumes, |stream, resumes| {
+            let this = stream.project();
+            cancel_blocking(Cancel::new(this.inner, this.finished, this.hint, resumes))
+        })
+    }
+}
+
+/// Polls the cancellation with a no-op waker until it is over.
+///
+/// Every poll spends at least one resume, so this terminates once the bound of the cancellation is reached.
+#[cfg(feature = "std")]
+fn cancel_blocking<F: Future + Unpin>(mut cancel: F) {
+    let mut cx = Context::from_waker(Waker::noop());
+
+    while Pin::new(&mut cancel).poll(&mut cx).is_pending() {}
+}
+
+#[cfg(feature = "std")]
+pin_project! {
+    /// Stream cancelling its generator on drop, created by `cancel_on_drop` on GenStreams.
+    pub struct CancelOnDrop<S> {
+        #[pin]
+        stream: S,
+        resumes: usize,
+        on_drop: fn(Pin<&mut S>, usize),
+    }
+
+    impl<S> PinnedDrop for CancelOnDrop<S> {
+        fn drop(this: Pin<&mut Self>) {
+            // Resuming a generator that has panicked would panic again.
+            if std::thread::panicking() {
+                return;
+            }
+
+            let this = this.project();
+            (this.on_drop)(this.stream, *this.resumes);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> CancelOnDrop<S> {
+    fn new(stream: S, resumes: usize, on_drop: fn(Pin<&mut S>, usize)) -> Self {
+        Self {
+            stream,
+            resumes,
+            on_drop,
+        }
+    }
+
+    /// Returns the wrapped stream, e.g. to `cancel` it explicitly.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().stream
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Stream> Stream for CancelOnDrop<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: FusedStream> FusedStream for CancelOnDrop<S> {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+/// Error produced as the last item of a [`GenTryStream`](struct.GenTryStream.html) or
+/// [`GenRecoverableStream`](struct.GenRecoverableStream.html) cancelled with `with_cancel`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("stream cancelled")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Cancelled {}
+
+pin_project! {
+    /// Stream cancelling its generator once a token resolves, created by `with_cancel` on GenStreams.
+    pub struct WithCancel<S: Stream, C> {
+        #[pin]
+        stream: S,
+        #[pin]
+        token: C,
+        cancellation: Option<Cancellation>,
+        last: Option<S::Item>,
+        finished: bool,
+    }
+}
+
+impl<S: Stream, C> WithCancel<S, C> {
+    fn new(stream: S, token: C, last: Option<S::Item>) -> Self {
+        Self {
+            stream,
+            token,
+            cancellation: None,
+            last,
+            finished: false,
+        }
+    }
+
+    /// Whether the token has resolved.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_some()
+    }
+
+    /// Returns the wrapped stream.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut S> {
+        self.project().stream
+    }
+}
+
+impl<S: Stream, C: Future> Stream for WithCancel<S, C> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.cancellation.is_none() && this.token.poll(cx).is_ready() {
+            *this.cancellation = Some(Cancellation::Interrupt);
+        }
+
+        let stream = this.stream;
+        let res = match *this.cancellation {
+            None => stream.poll_next(cx),
+            Some(mode) => {
+                let (res, outcome) = with_cancellation(mode, || stream.poll_next(cx));
+                *this.cancellation = Some(outcome);
+
+                if outcome == Cancellation::Abandoned {
+                    Poll::Ready(None)
+                } else {
+                    res
+                }
+            }
+        };
+
+        match res {
+            Poll::Ready(None) => {
+                *this.finished = true;
+
+                match this.cancellation {
+                    Some(_) => Poll::Ready(this.last.take()),
+                    None => Poll::Ready(None),
+                }
+            }
+            res => res,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            return (0, Some(0));
+        }
+
+        // The token may end the stream early, with the extra last item.
+        let (_, upper) = self.stream.size_hint();
+        (
+            0,
+            upper.and_then(|n| n.checked_add(self.last.is_some() as usize)),
+        )
+    }
+}
+
+impl<S: Stream, C: Future> FusedStream for WithCancel<S, C> {
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
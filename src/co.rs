@@ -0,0 +1,193 @@
+//! `async`-based generators that work on stable Rust.
+//!
+//! Instead of a `Coroutine`, write an `async` closure that receives a [`Co`](struct.Co.html) handle and
+//! yields values through it. The closure is wrapped in [`Gen`](struct.Gen.html), which can be put into any of GenStreams
+//! just like a nightly generator. Since the body is a regular `async` block, use `.await` instead of `gen_await!`.
+//!
+//! `from_co` constructors on every GenStream help type inference, most notably for `GenPerpetualStream`
+//! where the body must return `Infallible`.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use {
+//!     futures::{executor::block_on, prelude::*},
+//!     gen_stream::{GenPerpetualStream, GenTryStream},
+//! };
+//!
+//! let naturals = GenPerpetualStream::from_co(|co| async move {
+//!     let mut i = 0;
+//!     loop {
+//!         co.yield_(i).await;
+//!         i += 1;
+//!     }
+//! });
+//!
+//! let parsed = GenTryStream::from_co(|co| async move {
+//!     for s in &["1", "2", "x"] {
+//!         co.yield_(s.parse::<u32>()?).await;
+//!     }
+//!     Ok::<_, std::num::ParseIntError>(())
+//! });
+//!
+//! block_on(async {
+//!     assert_eq!(Box::pin(naturals).take(3).collect::<Vec<_>>().await, vec![0, 1, 2]);
+//!
+//!     let parsed = Box::pin(parsed).collect::<Vec<_>>().await;
+//!     assert_eq!(parsed[..2], [Ok(1), Ok(2)]);
+//!     assert!(parsed[2].is_err());
+//! });
+//! ```
+
+use {
+    crate::{
+        tls::{enter_resume, interrupt_await},
+        GenPerpetualStream, GenState, GenStream, GenTryStream, Resume,
+    },
+    core::{
+        convert::Infallible,
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    pin_project_lite::pin_project,
+    std::sync::{Arc, Mutex},
+};
+
+type Slot<Y> = Arc<Mutex<Option<Y>>>;
+
+/// Handle passed into the generator body, used to yield values.
+pub struct Co<Y> {
+    slot: Slot<Y>,
+}
+
+impl<Y> Co<Y> {
+    /// Yields a value to the consumer. The returned future must be awaited.
+    ///
+    /// Only one value can be in flight, so polling a second `yield_` before the first has completed,
+    /// e.g. with `join!`, panics instead of losing the value.
+    ///
+    /// ```rust,should_panic
+    /// use {
+    ///     futures::{executor::block_on, future, prelude::*},
+    ///     gen_stream::GenStream,
+    /// };
+    ///
+    /// let both = GenStream::from_co(|co| async move {
+    ///     future::join(co.yield_(1), co.yield_(2)).await;
+    /// });
+    ///
+    /// block_on(Box::pin(both).collect::<Vec<_>>());
+    /// ```
+    pub fn yield_(&self, value: Y) -> Yield<'_, Y> {
+        Yield {
+            co: self,
+            value: Some(value),
+        }
+    }
+}
+
+/// Future returned by [`Co::yield_`](struct.Co.html#method.yield_).
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Yield<'a, Y> {
+    co: &'a Co<Y>,
+    value: Option<Y>,
+}
+
+impl<Y> Future for Yield<'_, Y> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.value.take() {
+            Some(value) => {
+                let mut slot = self.co.slot.lock().unwrap();
+                assert!(
+                    slot.is_none(),
+                    "`yield_` polled while another yielded value is pending, await each `yield_` before the next"
+                );
+                *slot = Some(value);
+
+                Poll::Pending
+            }
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+impl<Y> Unpin for Yield<'_, Y> {}
+
+pin_project! {
+    /// Generator built from an `async` closure.
+    pub struct Gen<Y, F> {
+        slot: Slot<Y>,
+        #[pin]
+        fut: F,
+    }
+}
+
+impl<Y, F: Future> Gen<Y, F> {
+    /// Creates a new generator from a closure that receives a [`Co`](struct.Co.html) handle.
+    pub fn new(producer: impl FnOnce(Co<Y>) -> F) -> Self {
+        let slot = Slot::default();
+        let fut = producer(Co { slot: slot.clone() });
+
+        Self { slot, fut }
+    }
+}
+
+impl<Y, F: Future> Resume for Gen<Y, F> {
+    type Yield = Poll<Y>;
+    type Return = F::Output;
+
+    fn poll_resume(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Self::Yield, Self::Return> {
+        let this = self.project();
+        let (fut, slot) = (this.fut, this.slot);
+        enter_resume(|| {
+            if let Poll::Ready(v) = fut.poll(cx) {
+                return GenState::Complete(v);
+            }
+
+            match slot.lock().unwrap().take() {
+                Some(v) => GenState::Yielded(Poll::Ready(v)),
+                None => {
+                    // The body is waiting on something other than `yield_`.
+                    interrupt_await();
+                    GenState::Yielded(Poll::Pending)
+                }
+            }
+        })
+    }
+}
+
+impl<Y, F> GenStream<Gen<Y, F>>
+where
+    F: Future<Output = ()>,
+{
+    /// Creates a stream from an `async` closure.
+    pub fn from_co(producer: impl FnOnce(Co<Y>) -> F) -> Self {
+        Self::from(Gen::new(producer))
+    }
+}
+
+impl<Y, F> GenPerpetualStream<Gen<Y, F>>
+where
+    F: Future<Output = Infallible>,
+{
+    /// Creates a never-ending stream from an `async` closure.
+    pub fn from_co(producer: impl FnOnce(Co<Y>) -> F) -> Self {
+        Self::from(Gen::new(producer))
+    }
+}
+
+impl<Y, E, F> GenTryStream<Gen<Y, F>>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    /// Creates a fallible stream from an `async` closure.
+    pub fn from_co(producer: impl FnOnce(Co<Y>) -> F) -> Self {
+        Self::from(Gen::new(producer))
+    }
+}
@@ -0,0 +1,126 @@
+//! Coroutines that receive the task context as their resume argument.
+//!
+//! Instead of going through a thread-local slot, the wrappers in this module pass a [`ResumeCtx`](struct.ResumeCtx.html)
+//! into every `resume` call. The coroutine takes it as its argument and must rebind it from every `yield`,
+//! which `gen_await!(cx, fut)` does automatically. Size hints and cancellation still travel through thread-local
+//! slots if the `std` or `tls` feature is enabled; without them these coroutines need no global state at all.
+//!
+//! ```rust
+//! #![feature(coroutines)]
+//!
+//! use {
+//!     futures::{executor::block_on, prelude::*, task::Poll},
+//!     gen_stream::{ctx::ResumeCtx, gen_await, GenStream},
+//! };
+//!
+//! let stream = GenStream::from_ctx(Box::pin(#[coroutine] static move |mut cx: ResumeCtx| {
+//!     for i in 0..3 {
+//!         let doubled = gen_await!(cx, future::ready(i * 2));
+//!         cx = yield Poll::Ready(doubled);
+//!     }
+//! }));
+//!
+//! assert_eq!(block_on(stream.collect::<Vec<_>>()), vec![0, 2, 4]);
+//! ```
+//!
+//! Holding on to a `ResumeCtx` from an earlier resume is a bug: it keeps waking the task that polled back then,
+//! which is not necessarily the current one. Always write `cx = yield ...`.
+
+use {
+    crate::{GenState, Resume},
+    core::{
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        task::{Context, Waker},
+    },
+    pin_project_lite::pin_project,
+};
+
+#[cfg(any(feature = "std", feature = "tls"))]
+use crate::tls::enter_resume;
+
+/// Without thread-local storage generators can neither declare size hints nor be cancelled.
+#[cfg(not(any(feature = "std", feature = "tls")))]
+fn enter_resume<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    f()
+}
+
+/// Task context handle passed into a coroutine on every resume.
+///
+/// The handle owns a clone of the waker of the poll that resumed the coroutine, so it can be used
+/// without any `unsafe` and sent along with the coroutine.
+#[derive(Debug)]
+pub struct ResumeCtx(Waker);
+
+impl ResumeCtx {
+    fn new(cx: &Context<'_>) -> Self {
+        Self(cx.waker().clone())
+    }
+
+    /// Returns a task context waking the task of the resume this handle was received from.
+    pub fn context(&self) -> Context<'_> {
+        Context::from_waker(&self.0)
+    }
+}
+
+pin_project! {
+    /// Adapter resuming a coroutine with [`ResumeCtx`](struct.ResumeCtx.html).
+    pub struct CtxCoroutine<G> {
+        #[pin]
+        inner: G,
+    }
+}
+
+impl<G> From<G> for CtxCoroutine<G> {
+    fn from(inner: G) -> Self {
+        Self { inner }
+    }
+}
+
+impl<G: Coroutine<ResumeCtx>> Resume for CtxCoroutine<G> {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn poll_resume(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Self::Yield, Self::Return> {
+        enter_resume(|| match self.project().inner.resume(ResumeCtx::new(cx)) {
+            CoroutineState::Yielded(v) => GenState::Yielded(v),
+            CoroutineState::Complete(v) => GenState::Complete(v),
+        })
+    }
+}
+
+/// `GenStream` over a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+pub type GenStream<G> = crate::GenStream<CtxCoroutine<G>>;
+
+/// `GenPerpetualStream` over a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+pub type GenPerpetualStream<G> = crate::GenPerpetualStream<CtxCoroutine<G>>;
+
+/// `GenTryStream` over a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+pub type GenTryStream<G> = crate::GenTryStream<CtxCoroutine<G>>;
+
+impl<G> crate::GenStream<CtxCoroutine<G>> {
+    /// Creates a stream from a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+    pub fn from_ctx(inner: G) -> Self {
+        Self::from(CtxCoroutine::from(inner))
+    }
+}
+
+impl<G> crate::GenPerpetualStream<CtxCoroutine<G>> {
+    /// Creates a never-ending stream from a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+    pub fn from_ctx(inner: G) -> Self {
+        Self::from(CtxCoroutine::from(inner))
+    }
+}
+
+impl<G> crate::GenTryStream<CtxCoroutine<G>> {
+    /// Creates a fallible stream from a coroutine taking [`ResumeCtx`](struct.ResumeCtx.html).
+    pub fn from_ctx(inner: G) -> Self {
+        Self::from(CtxCoroutine::from(inner))
+    }
+}
@@ -0,0 +1,252 @@
+use {
+    crate::tls::set_task_context,
+    core::{
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    },
+    futures_core::{stream::FusedStream, Stream},
+    futures_sink::Sink,
+    pin_project_lite::pin_project,
+};
+
+/// Resume argument of a [`GenDuplex`](struct.GenDuplex.html) generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DuplexEvent<T> {
+    /// An item sent into the sink.
+    Item(T),
+    /// No incoming item, the generator is polled for output.
+    Poll,
+    /// The sink was closed, no more items will arrive.
+    Closed,
+}
+
+pin_project! {
+    /// Stream and sink driven by a single generator.
+    ///
+    /// The generator yields `Poll::Ready(Some(item))` to produce an outgoing item, `Poll::Ready(None)` to ask
+    /// for the next incoming item and `Poll::Pending` (as yielded by `gen_await!`) while it is waiting.
+    /// After asking for an item it is resumed with `DuplexEvent::Item` or, once the sink has been closed,
+    /// `DuplexEvent::Closed`. In all other cases, including the first resume, the argument is `DuplexEvent::Poll`.
+    ///
+    /// Both sides hold at most one item each, so the sink applies back-pressure until the previous outgoing item
+    /// is taken from the stream. Completion follows `GenTryStream`: returning `Ok(())` ends the stream and
+    /// discards further incoming items, while `Err(e)` is reported once to whichever side observes it first.
+    ///
+    /// ```rust
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{DuplexEvent, GenDuplex},
+    /// };
+    ///
+    /// let mut echo = GenDuplex::from(Box::pin(#[coroutine] static move |_: DuplexEvent<u32>| {
+    ///     yield Poll::Ready(Some("hello".to_string()));
+    ///
+    ///     loop {
+    ///         match yield Poll::Ready(None) {
+    ///             DuplexEvent::Item(0) => return Err("zero is not allowed"),
+    ///             DuplexEvent::Item(n) => {
+    ///                 yield Poll::Ready(Some(n.to_string()));
+    ///             }
+    ///             _ => return Ok(()),
+    ///         }
+    ///     }
+    /// }));
+    ///
+    /// block_on(async {
+    ///     assert_eq!(echo.next().await, Some(Ok("hello".to_string())));
+    ///
+    ///     echo.send(42).await.unwrap();
+    ///     assert_eq!(echo.next().await, Some(Ok("42".to_string())));
+    ///
+    ///     // The sink drives the generator to completion here, so it is the one to see the error.
+    ///     assert_eq!(echo.send(0).await, Err("zero is not allowed"));
+    ///     assert_eq!(echo.next().await, None);
+    /// });
+    /// ```
+    pub struct GenDuplex<G, I, O> {
+        #[pin]
+        inner: G,
+        input: Option<I>,
+        output: Option<O>,
+        wants_input: bool,
+        closing: bool,
+        finished: bool,
+        stream_waker: Option<Waker>,
+        sink_waker: Option<Waker>,
+    }
+}
+
+impl<G, I, O> From<G> for GenDuplex<G, I, O> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            input: None,
+            output: None,
+            wants_input: false,
+            closing: false,
+            finished: false,
+            stream_waker: None,
+            sink_waker: None,
+        }
+    }
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+impl<G, I, O, E> GenDuplex<G, I, O>
+where
+    G: Coroutine<DuplexEvent<I>, Yield = Poll<Option<O>>, Return = Result<(), E>>,
+{
+    /// Whether the generator waits for an incoming item that has not arrived yet.
+    fn starved(&self) -> bool {
+        self.wants_input && self.input.is_none() && !self.closing
+    }
+
+    /// Resumes the generator once, buffering its output. Returns the result on completion.
+    fn step(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<(), E>>> {
+        let this = self.project();
+        let event = if *this.wants_input {
+            match this.input.take() {
+                Some(item) => DuplexEvent::Item(item),
+                None => DuplexEvent::Closed,
+            }
+        } else {
+            DuplexEvent::Poll
+        };
+        *this.wants_input = false;
+
+        let inner = this.inner;
+        match set_task_context(cx, || inner.resume(event)) {
+            CoroutineState::Yielded(Poll::Ready(Some(item))) => {
+                *this.output = Some(item);
+                wake(this.stream_waker);
+                Poll::Ready(None)
+            }
+            CoroutineState::Yielded(Poll::Ready(None)) => {
+                *this.wants_input = true;
+                Poll::Ready(None)
+            }
+            CoroutineState::Yielded(Poll::Pending) => Poll::Pending,
+            CoroutineState::Complete(res) => {
+                *this.finished = true;
+                *this.input = None;
+                wake(this.stream_waker);
+                wake(this.sink_waker);
+                Poll::Ready(Some(res))
+            }
+        }
+    }
+
+    /// Drives the generator until it has consumed the incoming item and can make no further progress
+    /// on its own. With `close` set, drives it to completion.
+    fn poll_drive(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        close: bool,
+    ) -> Poll<Result<(), E>> {
+        loop {
+            if self.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            if self.output.is_some() {
+                if self.input.is_none() && !close {
+                    return Poll::Ready(Ok(()));
+                }
+
+                *self.as_mut().project().sink_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            if self.starved() {
+                return Poll::Ready(Ok(()));
+            }
+
+            if let Some(res) = futures_core::ready!(self.as_mut().step(cx)) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+}
+
+impl<G, I, O, E> Stream for GenDuplex<G, I, O>
+where
+    G: Coroutine<DuplexEvent<I>, Yield = Poll<Option<O>>, Return = Result<(), E>>,
+{
+    type Item = Result<O, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(item) = self.as_mut().project().output.take() {
+                wake(self.as_mut().project().sink_waker);
+                return Poll::Ready(Some(Ok(item)));
+            }
+
+            if self.finished {
+                return Poll::Ready(None);
+            }
+
+            if self.starved() {
+                *self.as_mut().project().stream_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            match futures_core::ready!(self.as_mut().step(cx)) {
+                None => {}
+                Some(Ok(())) => return Poll::Ready(None),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+impl<G, I, O, E> FusedStream for GenDuplex<G, I, O>
+where
+    G: Coroutine<DuplexEvent<I>, Yield = Poll<Option<O>>, Return = Result<(), E>>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished && self.output.is_none()
+    }
+}
+
+impl<G, I, O, E> Sink<I> for GenDuplex<G, I, O>
+where
+    G: Coroutine<DuplexEvent<I>, Yield = Poll<Option<O>>, Return = Result<(), E>>,
+{
+    type Error = E;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.input.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.poll_drive(cx, false)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        if !self.finished {
+            *self.as_mut().project().input = Some(item);
+            wake(self.as_mut().project().stream_waker);
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_drive(cx, false)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        *self.as_mut().project().closing = true;
+        wake(self.as_mut().project().stream_waker);
+
+        self.poll_drive(cx, true)
+    }
+}
@@ -0,0 +1,171 @@
+use {
+    crate::{Budget, GenState, Resume},
+    core::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::{stream::FusedStream, Stream},
+    pin_project_lite::pin_project,
+};
+
+pin_project! {
+    /// Future based on generator.
+    ///
+    /// Resolves to the generator's return value. Values yielded with `Poll::Ready` are treated as progress
+    /// reports and ignored, use [`GenProgressFuture`](struct.GenProgressFuture.html) to observe them.
+    pub struct GenFuture<G> {
+        #[pin]
+        inner: G,
+    }
+}
+
+impl<G> From<G> for GenFuture<G> {
+    fn from(inner: G) -> Self {
+        Self { inner }
+    }
+}
+
+impl<G, P> Future for GenFuture<G>
+where
+    G: Resume<Yield = Poll<P>>,
+{
+    type Output = G::Return;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut inner = self.project().inner;
+        loop {
+            match inner.as_mut().poll_resume(cx) {
+                GenState::Yielded(Poll::Ready(_)) => {}
+                GenState::Yielded(Poll::Pending) => return Poll::Pending,
+                GenState::Complete(v) => return Poll::Ready(v),
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// Future based on generator that reports progress.
+    ///
+    /// As a `Stream`, yields the progress values until the generator completes. As a `Future`, resolves to
+    /// the return value, skipping any progress that has not been consumed.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, GenProgressFuture},
+    /// };
+    ///
+    /// let mut sum = GenProgressFuture::from(Box::pin(#[coroutine] static move || {
+    ///     let mut sum = 0;
+    ///     for i in 1..=4 {
+    ///         sum += gen_await!(future::ready(i));
+    ///         yield Poll::Ready(i * 25);
+    ///     }
+    ///     sum
+    /// }));
+    ///
+    /// block_on(async {
+    ///     assert_eq!(sum.next().await, Some(25));
+    ///     assert_eq!(sum.next().await, Some(50));
+    ///     assert_eq!(sum.await, 10);
+    /// });
+    /// ```
+    pub struct GenProgressFuture<G, R> {
+        #[pin]
+        inner: G,
+        output: Option<R>,
+        finished: bool,
+        budget: Budget,
+    }
+}
+
+impl<G, R> GenProgressFuture<G, R> {
+    pub(crate) fn with_state(inner: G, output: Option<R>, finished: bool, budget: Budget) -> Self {
+        Self {
+            inner,
+            output,
+            finished,
+            budget,
+        }
+    }
+
+    /// Makes the future yield to the executor after `budget` consecutive ready progress values.
+    ///
+    /// See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
+}
+
+impl<G, R> From<G> for GenProgressFuture<G, R> {
+    fn from(inner: G) -> Self {
+        Self::with_state(inner, None, false, Budget::default())
+    }
+}
+
+impl<G, P, R> Stream for GenProgressFuture<G, R>
+where
+    G: Resume<Yield = Poll<P>, Return = R>,
+{
+    type Item = P;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        match this.inner.poll_resume(cx) {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(Some)
+            }
+            GenState::Complete(v) => {
+                *this.output = Some(v);
+                *this.finished = true;
+
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<G, P, R> FusedStream for GenProgressFuture<G, R>
+where
+    G: Resume<Yield = Poll<P>, Return = R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
+
+impl<G, P, R> Future for GenProgressFuture<G, R>
+where
+    G: Resume<Yield = Poll<P>, Return = R>,
+{
+    type Output = R;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        while !self.finished {
+            if self.as_mut().poll_next(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        Poll::Ready(
+            self.project()
+                .output
+                .take()
+                .expect("GenProgressFuture polled after completion"),
+        )
+    }
+}
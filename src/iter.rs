@@ -0,0 +1,291 @@
+//! Synchronous counterparts of GenStreams for generators yielding plain values.
+
+use {
+    crate::Budget,
+    core::{
+        convert::Infallible,
+        iter::FusedIterator,
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::{stream::FusedStream, Stream},
+    pin_project_lite::pin_project,
+};
+
+/// Simple generator-based iterator.
+///
+/// The generator yields items directly and must be `Unpin`, so `static` generators have to be pinned
+/// with `Box::pin` first.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use gen_stream::GenIter;
+///
+/// enum Tree {
+///     Leaf(u32),
+///     Node(Vec<Tree>),
+/// }
+///
+/// fn leaves(tree: &Tree) -> Box<dyn Iterator<Item = u32> + '_> {
+///     Box::new(GenIter::from(Box::pin(#[coroutine] static move || match tree {
+///         Tree::Leaf(v) => yield *v,
+///         Tree::Node(children) => {
+///             for child in children {
+///                 for v in leaves(child) {
+///                     yield v;
+///                 }
+///             }
+///         }
+///     })))
+/// }
+///
+/// let tree = Tree::Node(vec![Tree::Leaf(1), Tree::Node(vec![Tree::Leaf(2), Tree::Leaf(3)])]);
+/// assert_eq!(leaves(&tree).collect::<Vec<_>>(), vec![1, 2, 3]);
+/// ```
+pub struct GenIter<G> {
+    inner: G,
+    finished: bool,
+}
+
+impl<G> From<G> for GenIter<G> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            finished: false,
+        }
+    }
+}
+
+impl<G> Iterator for GenIter<G>
+where
+    G: Coroutine<Return = ()> + Unpin,
+{
+    type Item = G::Yield;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match Pin::new(&mut self.inner).resume(()) {
+            CoroutineState::Yielded(v) => Some(v),
+            CoroutineState::Complete(()) => {
+                self.finished = true;
+
+                None
+            }
+        }
+    }
+}
+
+impl<G> FusedIterator for GenIter<G> where G: Coroutine<Return = ()> + Unpin {}
+
+/// Iterator based on generator that never ends.
+pub struct GenPerpetualIter<G> {
+    inner: G,
+}
+
+impl<G> From<G> for GenPerpetualIter<G> {
+    fn from(inner: G) -> Self {
+        Self { inner }
+    }
+}
+
+/// The generator must return `!` or any other type convertible into `Infallible`.
+impl<G> Iterator for GenPerpetualIter<G>
+where
+    G: Coroutine + Unpin,
+    G::Return: Into<Infallible>,
+{
+    type Item = G::Yield;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match Pin::new(&mut self.inner).resume(()) {
+            CoroutineState::Yielded(v) => Some(v),
+            CoroutineState::Complete(_) => unreachable!(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+impl<G> FusedIterator for GenPerpetualIter<G>
+where
+    G: Coroutine + Unpin,
+    G::Return: Into<Infallible>,
+{
+}
+
+/// Iterator based on generator that may fail.
+///
+/// The first `Err` returned by the generator is produced as the last item.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use gen_stream::GenTryIter;
+///
+/// let tokens = GenTryIter::from(Box::pin(#[coroutine] static move || {
+///     for word in "let x = 1 ;".split(' ') {
+///         match word {
+///             "let" | "=" | ";" => yield word.to_string(),
+///             w if w.chars().all(char::is_alphanumeric) => yield format!("<{}>", w),
+///             w => return Err(format!("unexpected {:?}", w)),
+///         }
+///     }
+///     Ok(())
+/// }));
+///
+/// assert_eq!(tokens.collect::<Result<Vec<_>, _>>().unwrap(), vec!["let", "<x>", "=", "<1>", ";"]);
+/// ```
+pub struct GenTryIter<G> {
+    inner: G,
+    finished: bool,
+}
+
+impl<G> From<G> for GenTryIter<G> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            finished: false,
+        }
+    }
+}
+
+impl<G, E> Iterator for GenTryIter<G>
+where
+    G: Coroutine<Return = Result<(), E>> + Unpin,
+{
+    type Item = Result<G::Yield, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match Pin::new(&mut self.inner).resume(()) {
+            CoroutineState::Yielded(v) => Some(Ok(v)),
+            CoroutineState::Complete(res) => {
+                self.finished = true;
+
+                res.err().map(Err)
+            }
+        }
+    }
+}
+
+impl<G, E> FusedIterator for GenTryIter<G> where G: Coroutine<Return = Result<(), E>> + Unpin {}
+
+pin_project! {
+    /// Stream over a synchronous generator, ready on every poll.
+    ///
+    /// Lifts a generator yielding plain values into a `Stream` without going through `Poll`.
+    ///
+    /// ```rust
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*},
+    ///     gen_stream::GenIterStream,
+    /// };
+    ///
+    /// let squares = GenIterStream::from(#[coroutine] static move || {
+    ///     for i in 1..4 {
+    ///         yield i * i;
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(block_on(squares.collect::<Vec<_>>()), vec![1, 4, 9]);
+    /// ```
+    pub struct GenIterStream<G> {
+        #[pin]
+        inner: G,
+        finished: bool,
+        budget: Budget,
+    }
+}
+
+impl<G> GenIterStream<G> {
+    /// Makes the stream yield to the executor after `budget` consecutive items.
+    ///
+    /// Without a budget the stream never returns `Poll::Pending`, so a consumer looping over it keeps the
+    /// executor busy until the generator completes. See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    ///
+    /// ```rust
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*},
+    ///     gen_stream::GenIterStream,
+    /// };
+    ///
+    /// let mut naturals = GenIterStream::from(#[coroutine] || {
+    ///     for i in 0..3 {
+    ///         yield i;
+    ///     }
+    /// })
+    /// .with_budget(2);
+    ///
+    /// assert_eq!(naturals.next().now_or_never(), Some(Some(0)));
+    /// assert_eq!(naturals.next().now_or_never(), Some(Some(1)));
+    /// assert_eq!(naturals.next().now_or_never(), None);
+    /// assert_eq!(block_on(naturals.collect::<Vec<_>>()), vec![2]);
+    /// ```
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
+}
+
+impl<G> From<G> for GenIterStream<G> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            finished: false,
+            budget: Budget::default(),
+        }
+    }
+}
+
+impl<G> Stream for GenIterStream<G>
+where
+    G: Coroutine<Return = ()>,
+{
+    type Item = G::Yield;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        match this.inner.resume(()) {
+            CoroutineState::Yielded(v) => {
+                this.budget.record(true);
+                Poll::Ready(Some(v))
+            }
+            CoroutineState::Complete(()) => {
+                *this.finished = true;
+
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<G> FusedStream for GenIterStream<G>
+where
+    G: Coroutine<Return = ()>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
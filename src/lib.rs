@@ -8,7 +8,7 @@
 //! Just write your own generator and wrap it in one of GenStreams.
 //!
 //! ## How can I use this?
-//! You need Rust nightly 2019-05-09 or later.
+//! You need a recent Rust nightly that provides the `Coroutine` trait and the `#[coroutine]` attribute.
 //!
 //! Add this to Cargo.toml:
 //!
@@ -16,14 +16,70 @@
 //! gen-stream = "0.2"
 //! ```
 //!
+//! ## Macros
+//! With the default `macros` feature, `#[gen_stream]`, `#[gen_try_stream]` and `#[gen_perpetual_stream]` attributes
+//! turn a function with bare `yield x` statements into one returning `impl Stream`:
+//!
+#![cfg_attr(feature = "macros", doc = " ```rust")]
+#![cfg_attr(not(feature = "macros"), doc = " ```ignore")]
+//! #![feature(coroutines)]
+//!
+//! use {
+//!     futures::{executor::block_on, prelude::*},
+//!     gen_stream::{gen_stream, gen_try_stream},
+//! };
+//!
+//! #[gen_stream(item = u32)]
+//! fn countdown(from: u32) {
+//!     for i in (0..from).rev() {
+//!         yield i;
+//!     }
+//! }
+//!
+//! #[gen_try_stream(ok = u32, error = std::num::ParseIntError)]
+//! fn parse(input: Vec<&'static str>) {
+//!     for s in input {
+//!         yield s.parse()?;
+//!     }
+//! }
+//!
+//! block_on(async {
+//!     assert_eq!(countdown(3).collect::<Vec<_>>().await, vec![2, 1, 0]);
+//!
+//!     let parsed = parse(vec!["1", "x", "3"]).collect::<Vec<_>>().await;
+//!     assert_eq!(parsed.len(), 2);
+//!     assert_eq!(parsed[0], Ok(1));
+//!     assert!(parsed[1].is_err());
+//! });
+//! ```
+//!
+//! ## Stable Rust
+//! Disable the default `nightly` feature and enable `co` to get an `async`-based backend that works on stable Rust:
+//!
+//! ```toml
+//! gen-stream = { version = "0.2", default-features = false, features = ["co"] }
+//! ```
+//!
+//! See the [`co`](co/index.html) module for details.
+//!
+//! ## `no_std`
+//! Disable the default `std` feature to use GenStreams without the standard library. Plain generators
+//! and `gen_await!(fut)` pass the task context through a thread-local slot, as do size hints and cancellation.
+//! Without `std` they need the `tls` feature, which keeps these slots in `#[thread_local]` statics. Otherwise only
+//! the coroutines from the [`ctx`](ctx/index.html) module can be used, which need no global state at all.
+//! The `alloc` feature is required by the macros.
+//!
+//! ```toml
+//! gen-stream = { version = "0.2", default-features = false, features = ["nightly", "tls"] }
+//! ```
+//!
 //! ## Example
 //!
-//! ```rust
-//! #![feature(async_await)]
+#![cfg_attr(feature = "nightly", doc = " ```rust")]
+#![cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
 //! #![feature(never_type)]
-//! #![feature(generators)]
-//! #![feature(generator_trait)]
-//! #![feature(gen_future)]
+//! #![feature(coroutines)]
+//! #![feature(coroutine_trait)]
 //!
 //! use {
 //!     futures::{
@@ -32,12 +88,12 @@
 //!         task::Poll,
 //!     },
 //!     gen_stream::{gen_await, GenPerpetualStream},
-//!     std::{ops::Generator, time::{Duration, SystemTime}},
+//!     std::{ops::Coroutine, time::{Duration, SystemTime}},
 //!     tokio::{runtime::current_thread::Runtime, timer::Interval},
 //! };
 //!
-//! fn current_time() -> impl Generator<Yield = Poll<SystemTime>, Return = !> {
-//!     static move || {
+//! fn current_time() -> impl Coroutine<Yield = Poll<SystemTime>, Return = !> {
+//!     #[coroutine] static move || {
 //!         let mut i = Interval::new_interval(Duration::from_millis(500)).compat();
 //!
 //!         loop {
@@ -64,108 +120,1287 @@
 //! }
 //! ```
 
-#![feature(generator_trait)]
-#![feature(gen_future)]
-#![feature(never_type)]
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "nightly", feature(coroutine_trait))]
+#![cfg_attr(all(feature = "tls", not(feature = "std")), feature(thread_local))]
 
 use {
     core::{
-        ops::{Generator, GeneratorState},
+        convert::Infallible,
+        fmt,
         pin::Pin,
         task::{Context, Poll},
     },
     futures_core::*,
-    pin_utils::unsafe_pinned,
-    std::future::set_task_context,
+    pin_project_lite::pin_project,
 };
 
+#[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+use {
+    crate::tls::set_task_context,
+    core::ops::{Coroutine, CoroutineState},
+};
+
+#[cfg(any(feature = "std", feature = "tls"))]
+use crate::tls::with_size_hint;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(any(feature = "std", feature = "tls"))]
+mod tls;
+
+#[cfg(feature = "co")]
+pub mod co;
+
+#[cfg(feature = "nightly")]
+pub mod ctx;
+
+mod future;
+
+pub use crate::future::{GenFuture, GenProgressFuture};
+
+#[cfg(feature = "alloc")]
+mod boxed;
+
+#[cfg(feature = "alloc")]
+pub use crate::boxed::{
+    BoxGenPerpetualStream, BoxGenStream, BoxGenTryStream, LocalBoxGenPerpetualStream,
+    LocalBoxGenStream, LocalBoxGenTryStream,
+};
+
+#[cfg(any(feature = "std", feature = "tls"))]
+mod cancel;
+
+#[cfg(any(feature = "std", feature = "tls"))]
+pub use crate::cancel::{Cancel, Cancelled, WithCancel};
+
+#[cfg(feature = "std")]
+pub use crate::cancel::CancelOnDrop;
+
+#[cfg(feature = "std")]
+mod panic;
+
+#[cfg(feature = "nightly")]
+mod select;
+#[cfg(feature = "nightly")]
+mod yield_from;
+
+#[cfg(feature = "std")]
+pub use crate::panic::{CatchPanics, PanicError};
+
+#[cfg(feature = "nightly")]
+mod iter;
+
+#[cfg(feature = "nightly")]
+pub use crate::iter::{GenIter, GenIterStream, GenPerpetualIter, GenTryIter};
+
+#[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+mod duplex;
+#[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+mod sink;
+
+#[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+pub use crate::{
+    duplex::{DuplexEvent, GenDuplex},
+    sink::GenSink,
+};
+
+#[cfg(feature = "macros")]
+pub use gen_stream_macros::{gen_perpetual_stream, gen_stream, gen_try_stream};
+
+/// Inline generator stream expressions.
+///
+/// These live in a separate module because `gen_stream!` would clash with the `#[gen_stream]` attribute.
+///
+/// `gen_stream! { ... }` evaluates to a `GenStream` and `try_gen_stream! { ... }` to a `GenTryStream`
+/// wrapping a pinned `static move` generator made of the block. As with the attributes, `yield x` is
+/// rewritten into `yield Poll::Ready(x)` and `gen_await!` may be used. In `try_gen_stream!` the `?` operator
+/// ends the stream with an error, whose type must be inferable from the context.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*},
+///     gen_stream::{block::{gen_stream, try_gen_stream}, gen_await},
+///     std::num::ParseIntError,
+/// };
+///
+/// fn numbers(input: Option<&'static str>) -> Box<dyn Stream<Item = Result<u32, ParseIntError>> + Unpin> {
+///     match input {
+///         Some(s) => Box::new(try_gen_stream! {
+///             for part in s.split(',') {
+///                 yield part.parse()?;
+///             }
+///         }),
+///         None => Box::new(gen_stream! {
+///             let answer = gen_await!(future::ready(42));
+///             yield Ok(answer);
+///         }),
+///     }
+/// }
+///
+/// block_on(async {
+///     assert_eq!(numbers(None).collect::<Vec<_>>().await, vec![Ok(42)]);
+///     assert_eq!(numbers(Some("1,2")).collect::<Vec<_>>().await, vec![Ok(1), Ok(2)]);
+///     assert_eq!(numbers(Some("1,x,3")).collect::<Vec<_>>().await.len(), 2);
+/// });
+/// ```
+#[cfg(feature = "macros")]
+pub mod block {
+    pub use gen_stream_macros::{
+        gen_stream_block as gen_stream, try_gen_stream_block as try_gen_stream,
+    };
+}
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(any(feature = "std", feature = "tls"))]
+    pub use crate::tls::{
+        enter_cleanup, interrupt_await, interrupt_next, is_cancelled, set_size_hint,
+    };
+    #[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+    pub use crate::tls::{poll_with_task_context, with_task_context};
+    #[cfg(feature = "nightly")]
+    pub use crate::{select::Select, yield_from::YieldFrom};
+    pub use futures_core::Stream;
+
+    #[cfg(feature = "alloc")]
+    pub use alloc::boxed::Box;
+
+    use core::task::{Context, Poll};
+
+    /// Without thread-local storage no await is ever interrupted.
+    #[cfg(not(any(feature = "std", feature = "tls")))]
+    pub fn interrupt_await() {}
+
+    #[cfg(not(any(feature = "std", feature = "tls")))]
+    pub fn interrupt_next() -> bool {
+        false
+    }
+
+    /// Pins down the signature of a `gen_poll!` closure.
+    pub fn poll_fn<F, T>(f: F) -> F
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<T>,
+    {
+        f
+    }
+
+    /// Lets the `impl Stream` returned by the attribute macros capture the lifetimes of the arguments.
+    pub trait Captures<'a> {}
+
+    impl<T: ?Sized> Captures<'_> for T {}
+}
+
 /// Like await!() but for bare generators.
+///
+/// `gen_await!(fut)` picks up the task context set by the enclosing GenStream. Inside a coroutine taking
+/// [`ResumeCtx`](ctx/struct.ResumeCtx.html), use `gen_await!(cx, fut)` instead, where `cx` is the mutable binding
+/// holding the context.
+///
+/// The future is pinned inside the generator, so the generator must be `static`:
+///
+/// ```rust,compile_fail,E0626
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{future, task::Poll},
+///     gen_stream::{gen_await, GenStream},
+/// };
+///
+/// let stream = GenStream::from(Box::pin(#[coroutine] move || {
+///     let x = gen_await!(future::ready(1));
+///     yield Poll::Ready(x);
+/// }));
+/// ```
+#[cfg(feature = "nightly")]
 #[macro_export]
 macro_rules! gen_await {
+    ($cx:ident, $e:expr) => {{
+        let mut pinned = ::core::pin::pin!($e);
+        loop {
+            if let ::core::task::Poll::Ready(x) =
+                ::core::future::Future::poll(pinned.as_mut(), &mut $cx.context())
+            {
+                break x;
+            }
+            $crate::__private::interrupt_await();
+            $cx = yield ::core::task::Poll::Pending;
+        }
+    }};
     ($e:expr) => {{
-        let mut pinned = $e;
+        let mut pinned = ::core::pin::pin!($e);
+        loop {
+            if let ::core::task::Poll::Ready(x) =
+                $crate::__private::poll_with_task_context(pinned.as_mut())
+            {
+                break x;
+            }
+            $crate::__private::interrupt_await();
+            yield ::core::task::Poll::Pending;
+        }
+    }};
+}
+
+/// Awaits the next item of a stream inside a generator, evaluating to `Option<T>`.
+///
+/// The stream is polled directly with the task context of the enclosing GenStream and must be `Unpin`,
+/// pin it with `Box::pin` otherwise. As with `gen_await!`, use `gen_await_next!(cx, stream)` inside
+/// a coroutine taking [`ResumeCtx`](ctx/struct.ResumeCtx.html). When the stream is being cancelled with
+/// [`with_cancel`](struct.GenStream.html#method.with_cancel) or [`cancel`](struct.GenStream.html#method.cancel),
+/// it evaluates to `None` instead of waiting.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_await_next, GenStream},
+/// };
+///
+/// let mut input = stream::iter(vec![1, 2, 3, 4]);
+///
+/// let pairs = GenStream::from(Box::pin(#[coroutine] static move || {
+///     while let Some(a) = gen_await_next!(input) {
+///         let b = gen_await_next!(input).unwrap_or(0);
+///         yield Poll::Ready(a + b);
+///     }
+/// }));
+///
+/// assert_eq!(block_on(pairs.collect::<Vec<_>>()), vec![3, 7]);
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_await_next {
+    ($cx:ident, $s:expr) => {{
+        let stream = &mut $s;
+        loop {
+            if let ::core::task::Poll::Ready(x) = $crate::__private::Stream::poll_next(
+                ::core::pin::Pin::new(&mut *stream),
+                &mut $cx.context(),
+            ) {
+                break x;
+            }
+            if $crate::__private::interrupt_next() {
+                break ::core::option::Option::None;
+            }
+            $cx = yield ::core::task::Poll::Pending;
+        }
+    }};
+    ($s:expr) => {{
+        let stream = &mut $s;
         loop {
-            if let ::core::task::Poll::Ready(x) = std::future::poll_with_tls_context(unsafe {
-                ::core::pin::Pin::new_unchecked(&mut pinned)
+            if let ::core::task::Poll::Ready(x) = $crate::__private::with_task_context(|cx| {
+                $crate::__private::Stream::poll_next(::core::pin::Pin::new(&mut *stream), cx)
             }) {
                 break x;
             }
+            if $crate::__private::interrupt_next() {
+                break ::core::option::Option::None;
+            }
             yield ::core::task::Poll::Pending;
         }
     }};
 }
 
-/// Simple generator-based stream.
-pub struct GenStream<G> {
-    inner: G,
+/// Runs the body for every item of a stream inside a generator.
+///
+/// `gen_for!(pat in stream => { ... })` is a `while let` loop over [`gen_await_next!`](macro.gen_await_next.html),
+/// so `break` and `continue` work as usual. Like `for`, it takes the stream by value; pass `&mut stream` to keep it. Inside a coroutine taking [`ResumeCtx`](ctx/struct.ResumeCtx.html),
+/// write `gen_for!(cx, pat in stream => { ... })`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_for, GenStream},
+/// };
+///
+/// let lines = stream::iter(vec!["a=1", "# comment", "b=2", "end", "c=3"]);
+///
+/// let keys = GenStream::from(Box::pin(#[coroutine] static move || {
+///     gen_for!(line in lines => {
+///         if line == "end" {
+///             break;
+///         }
+///         if let Some((key, _)) = line.split_once('=') {
+///             yield Poll::Ready(key);
+///         }
+///     });
+/// }));
+///
+/// assert_eq!(block_on(keys.collect::<Vec<_>>()), vec!["a", "b"]);
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_for {
+    ($cx:ident, $pat:pat in $s:expr => $body:block) => {{
+        let mut stream = $s;
+        while let ::core::option::Option::Some($pat) = $crate::gen_await_next!($cx, stream) $body
+    }};
+    ($pat:pat in $s:expr => $body:block) => {{
+        let mut stream = $s;
+        while let ::core::option::Option::Some($pat) = $crate::gen_await_next!(stream) $body
+    }};
+}
+
+/// Re-yields every item of an inner stream or generator, like Python's `yield from`.
+///
+/// The inner value is taken by value and pinned in place, so `static` generators need not be boxed.
+/// For a `Stream` the macro evaluates to `()` once it ends; for a generator yielding `Poll<T>` (including
+/// `co` and `ctx` generators) it evaluates to the generator's return value. Inside a coroutine taking
+/// [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_yield_from!(cx, inner)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+/// #![feature(coroutine_trait)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_yield_from, GenStream},
+///     std::ops::Coroutine,
+/// };
+///
+/// fn section(name: &'static str, len: u32) -> impl Coroutine<Yield = Poll<String>, Return = u32> {
+///     #[coroutine] static move || {
+///         for i in 0..len {
+///             yield Poll::Ready(format!("{}.{}", name, i));
+///         }
+///         len
+///     }
+/// }
+///
+/// let doc = GenStream::from(Box::pin(#[coroutine] static move || {
+///     gen_yield_from!(stream::iter(vec!["title".to_string()]));
+///
+///     let total = gen_yield_from!(section("a", 2)) + gen_yield_from!(section("b", 1));
+///     yield Poll::Ready(format!("{} lines", total));
+/// }));
+///
+/// assert_eq!(
+///     block_on(doc.collect::<Vec<_>>()),
+///     vec!["title", "a.0", "a.1", "b.0", "3 lines"],
+/// );
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_yield_from {
+    ($cx:ident, $e:expr) => {{
+        let mut pinned = ::core::pin::pin!($e);
+        loop {
+            match $crate::__private::YieldFrom::poll_yield_from(pinned.as_mut(), &mut $cx.context())
+            {
+                $crate::GenState::Yielded(v) => {
+                    if v.is_pending() {
+                        $crate::__private::interrupt_await();
+                    }
+                    $cx = yield v;
+                }
+                $crate::GenState::Complete(v) => break v,
+            }
+        }
+    }};
+    ($e:expr) => {{
+        let mut pinned = ::core::pin::pin!($e);
+        loop {
+            match $crate::__private::with_task_context(|cx| {
+                $crate::__private::YieldFrom::poll_yield_from(pinned.as_mut(), cx)
+            }) {
+                $crate::GenState::Yielded(v) => {
+                    if v.is_pending() {
+                        $crate::__private::interrupt_await();
+                    }
+                    yield v;
+                }
+                $crate::GenState::Complete(v) => break v,
+            }
+        }
+    }};
+}
+
+/// Waits for the first of several futures or streams inside a generator and runs its branch.
+///
+/// Each branch reads `pattern = expression => body` and branches are separated by commas. A branch completes
+/// when its future resolves, binding the output, or when its stream produces the next item, binding the `Option`.
+/// Patterns must be irrefutable.
+/// The branches are polled in order with the task context of the enclosing GenStream, so earlier branches win
+/// ties, and `Poll::Pending` is yielded while none is ready. The macro evaluates to the body of the winning branch,
+/// in which `break`, `continue` and `yield` refer to the surrounding generator code.
+///
+/// Every expression is evaluated once and pinned in place. To race the same future or stream again, e.g. in a loop,
+/// pass it as `&mut` (it must be `Unpin` then) and make sure it is not polled again after it has completed.
+/// Inside a coroutine taking [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_select!(cx, ...)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{channel::oneshot, executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_select, GenStream},
+/// };
+///
+/// let (shutdown, mut shutdown_rx) = oneshot::channel::<()>();
+/// let mut ticks = stream::iter(1..=3).chain(stream::pending());
+///
+/// let mut stream = GenStream::from(Box::pin(#[coroutine] static move || {
+///     loop {
+///         gen_select! {
+///             _ = &mut shutdown_rx => break,
+///             tick = &mut ticks => match tick {
+///                 Some(tick) => yield Poll::Ready(tick),
+///                 None => break,
+///             },
+///         }
+///     }
+/// }));
+///
+/// block_on(async {
+///     assert_eq!(stream.by_ref().take(3).collect::<Vec<_>>().await, vec![1, 2, 3]);
+///     shutdown.send(()).unwrap();
+///     assert_eq!(stream.next().await, None);
+/// });
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_select {
+    ($cx:ident, $($pat:pat = $e:expr => $body:expr),+ $(,)?) => {
+        $crate::__gen_select!(@bind ($cx) [] $($pat = $e => $body,)+)
+    };
+    ($($pat:pat = $e:expr => $body:expr),+ $(,)?) => {
+        $crate::__gen_select!(@bind () [] $($pat = $e => $body,)+)
+    };
+}
+
+#[cfg(feature = "nightly")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __gen_select {
+    // Every recursion step declares its own hygienic `fut` and `out` bindings.
+    (@bind $mode:tt [$($done:tt)*] $pat:pat = $e:expr => $body:expr, $($rest:tt)*) => {{
+        let mut fut = ::core::pin::pin!($e);
+        let mut out = ::core::option::Option::None;
+        $crate::__gen_select!(@bind $mode [$($done)* (fut, out, $pat, $body)] $($rest)*)
+    }};
+    (@bind $mode:tt [$(($fut:ident, $out:ident, $pat:pat, $body:expr))+]) => {{
+        loop {
+            $(
+                if let ::core::task::Poll::Ready(v) = $crate::__gen_select!(@poll $mode $fut) {
+                    $out = ::core::option::Option::Some(v);
+                    break;
+                }
+            )+
+            $crate::__gen_select!(@pending $mode);
+        }
+        $crate::__gen_select!(@dispatch $(($out, $pat, $body))+)
+    }};
+    (@poll () $fut:ident) => {
+        $crate::__private::with_task_context(|cx| $crate::__private::Select::poll_select($fut.as_mut(), cx))
+    };
+    (@poll ($cx:ident) $fut:ident) => {
+        $crate::__private::Select::poll_select($fut.as_mut(), &mut $cx.context())
+    };
+    (@pending ()) => {{
+        $crate::__private::interrupt_await();
+        yield ::core::task::Poll::Pending
+    }};
+    (@pending ($cx:ident)) => {{
+        $crate::__private::interrupt_await();
+        $cx = yield ::core::task::Poll::Pending
+    }};
+    (@dispatch ($out:ident, $pat:pat, $body:expr) $($rest:tt)+) => {
+        match $out {
+            ::core::option::Option::Some($pat) => $body,
+            ::core::option::Option::None => $crate::__gen_select!(@dispatch $($rest)+),
+        }
+    };
+    (@dispatch ($out:ident, $pat:pat, $body:expr)) => {
+        match $out {
+            ::core::option::Option::Some($pat) => $body,
+            ::core::option::Option::None => unreachable!(),
+        }
+    };
+}
+
+/// Awaits several futures concurrently inside a generator, evaluating to a tuple of their outputs.
+///
+/// All futures are polled with the task context of the enclosing GenStream until every one of them
+/// has completed, yielding `Poll::Pending` in between. Inside a coroutine taking
+/// [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_join!(cx; a, b)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{channel::oneshot, executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_join, GenStream},
+/// };
+///
+/// let (user_tx, user) = oneshot::channel();
+/// let (posts_tx, posts) = oneshot::channel();
+///
+/// let mut pages = GenStream::from(Box::pin(#[coroutine] static move || {
+///     let (user, posts, footer) = gen_join!(user, posts, future::ready("footer"));
+///     yield Poll::Ready(format!("{}: {} posts, {}", user.unwrap(), posts.unwrap(), footer));
+/// }));
+///
+/// block_on(async {
+///     assert_eq!(pages.next().now_or_never(), None);
+///     posts_tx.send(2).unwrap();
+///     user_tx.send("alice").unwrap();
+///     assert_eq!(pages.next().await, Some("alice: 2 posts, footer".to_string()));
+/// });
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_join {
+    ($cx:ident; $($e:expr),+ $(,)?) => {
+        $crate::__gen_join!(@bind join ($cx) [] $($e,)+)
+    };
+    ($($e:expr),+ $(,)?) => {
+        $crate::__gen_join!(@bind join () [] $($e,)+)
+    };
+}
+
+/// Awaits several fallible futures concurrently inside a generator, stopping at the first error.
+///
+/// Evaluates to `Ok` with a tuple of the outputs once every future has resolved to `Ok`, or to the first `Err`,
+/// after which the remaining futures are dropped unfinished. Combined with `?` this ends a `GenTryStream`
+/// with the error. Inside a coroutine taking [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_try_join!(cx; a, b)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_try_join, GenTryStream},
+/// };
+///
+/// let fetch = |id: u32| async move {
+///     if id == 0 {
+///         Err(format!("no such id: {}", id))
+///     } else {
+///         Ok(id * 10)
+///     }
+/// };
+///
+/// let rows = GenTryStream::from(Box::pin(#[coroutine] static move || {
+///     let (a, b) = gen_try_join!(fetch(1), fetch(2))?;
+///     yield Poll::Ready(a + b);
+///     let (c, d) = gen_try_join!(fetch(3), fetch(0))?;
+///     yield Poll::Ready(c + d);
+///     Ok(())
+/// }));
+///
+/// assert_eq!(
+///     block_on(rows.collect::<Vec<_>>()),
+///     vec![Ok(30), Err("no such id: 0".to_string())]
+/// );
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_try_join {
+    ($cx:ident; $($e:expr),+ $(,)?) => {
+        $crate::__gen_join!(@bind try ($cx) [] $($e,)+)
+    };
+    ($($e:expr),+ $(,)?) => {
+        $crate::__gen_join!(@bind try () [] $($e,)+)
+    };
+}
+
+#[cfg(feature = "nightly")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __gen_join {
+    // Every recursion step declares its own hygienic `fut` and `out` bindings.
+    (@bind $kind:ident $mode:tt [$($done:tt)*] $e:expr, $($rest:tt)*) => {{
+        let mut fut = ::core::pin::pin!($e);
+        let mut out = ::core::option::Option::None;
+        $crate::__gen_join!(@bind $kind $mode [$($done)* (fut, out)] $($rest)*)
+    }};
+    (@bind join $mode:tt [$(($fut:ident, $out:ident))+]) => {{
+        loop {
+            let mut done = true;
+            $(
+                if $out.is_none() {
+                    match $crate::__gen_join!(@poll $mode $fut) {
+                        ::core::task::Poll::Ready(v) => $out = ::core::option::Option::Some(v),
+                        ::core::task::Poll::Pending => done = false,
+                    }
+                }
+            )+
+            if done {
+                break;
+            }
+            $crate::__gen_join!(@pending $mode);
+        }
+        ($($out.unwrap(),)+)
+    }};
+    (@bind try $mode:tt [$(($fut:ident, $out:ident))+]) => {{
+        let res = loop {
+            let mut done = true;
+            $(
+                if $out.is_none() {
+                    match $crate::__gen_join!(@poll $mode $fut) {
+                        ::core::task::Poll::Ready(::core::result::Result::Ok(v)) => {
+                            $out = ::core::option::Option::Some(v)
+                        }
+                        ::core::task::Poll::Ready(::core::result::Result::Err(e)) => {
+                            break ::core::result::Result::Err(e)
+                        }
+                        ::core::task::Poll::Pending => done = false,
+                    }
+                }
+            )+
+            if done {
+                break ::core::result::Result::Ok(());
+            }
+            $crate::__gen_join!(@pending $mode);
+        };
+        res.map(|()| ($($out.unwrap(),)+))
+    }};
+    (@poll () $fut:ident) => {
+        $crate::__private::poll_with_task_context($fut.as_mut())
+    };
+    (@poll ($cx:ident) $fut:ident) => {
+        ::core::future::Future::poll($fut.as_mut(), &mut $cx.context())
+    };
+    (@pending ()) => {{
+        $crate::__private::interrupt_await();
+        yield ::core::task::Poll::Pending
+    }};
+    (@pending ($cx:ident)) => {{
+        $crate::__private::interrupt_await();
+        $cx = yield ::core::task::Poll::Pending
+    }};
+}
+
+/// Calls a `poll_*` style closure with the task context until it is ready, evaluating to its output.
+///
+/// `gen_poll!(|cx| io.poll_read(cx, &mut buf))` gives generators access to any poll-based API, not just futures.
+/// The closure receives the task context of the enclosing GenStream and is called again on every resume
+/// while it returns `Poll::Pending`, which is yielded in the meantime. Inside a coroutine taking
+/// [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_poll!(cx, |cx| ...)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{channel::mpsc, executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_poll, GenTryStream},
+/// };
+///
+/// let (mut tx, rx) = mpsc::channel(0);
+///
+/// let sent = GenTryStream::from(Box::pin(#[coroutine] static move || {
+///     for i in 0..3 {
+///         gen_poll!(|cx| tx.poll_ready(cx))?;
+///         tx.start_send(i)?;
+///         yield Poll::Ready(i);
+///     }
+///     drop(tx);
+///     Ok::<_, mpsc::SendError>(())
+/// }));
+///
+/// let (sent, received) = block_on(future::join(sent.collect::<Vec<_>>(), rx.collect::<Vec<_>>()));
+/// assert_eq!(sent, vec![Ok(0), Ok(1), Ok(2)]);
+/// assert_eq!(received, vec![0, 1, 2]);
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_poll {
+    ($cx:ident, $f:expr) => {{
+        let mut f = $crate::__private::poll_fn($f);
+        loop {
+            if let ::core::task::Poll::Ready(x) = f(&mut $cx.context()) {
+                break x;
+            }
+            $crate::__private::interrupt_await();
+            $cx = yield ::core::task::Poll::Pending;
+        }
+    }};
+    ($f:expr) => {{
+        let mut f = $crate::__private::poll_fn($f);
+        loop {
+            if let ::core::task::Poll::Ready(x) = $crate::__private::with_task_context(|cx| f(cx)) {
+                break x;
+            }
+            $crate::__private::interrupt_await();
+            yield ::core::task::Poll::Pending;
+        }
+    }};
+}
+
+/// Yields `Poll::Pending` once, waking the task first, to let the executor run other tasks.
+///
+/// Use it in long-running loops that would otherwise never return control to the executor. Inside a
+/// coroutine taking [`ResumeCtx`](ctx/struct.ResumeCtx.html), write `gen_yield_now!(cx)`.
+///
+/// ```rust
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_yield_now, GenStream},
+/// };
+///
+/// let mut sums = GenStream::from(Box::pin(#[coroutine] static move || {
+///     let mut sum = 0u64;
+///     for i in 0..3_000u64 {
+///         sum += i;
+///         if i % 1_000 == 999 {
+///             gen_yield_now!();
+///         }
+///     }
+///     yield Poll::Ready(sum);
+/// }));
+///
+/// assert_eq!(sums.next().now_or_never(), None);
+/// assert_eq!(block_on(sums.next()), Some(4_498_500));
+/// ```
+#[cfg(feature = "nightly")]
+#[macro_export]
+macro_rules! gen_yield_now {
+    ($cx:ident) => {{
+        $cx.context().waker().wake_by_ref();
+        $cx = yield ::core::task::Poll::Pending;
+    }};
+    () => {{
+        $crate::__private::with_task_context(|cx| cx.waker().wake_by_ref());
+        yield ::core::task::Poll::Pending;
+    }};
+}
+
+/// Runs async cleanup and completes the generator if the stream is being cancelled.
+///
+/// Place it at the points where the generator may be interrupted, typically right after a `yield`. Normally it does nothing.
+/// When the stream is cancelled with [`cancel`](struct.GenStream.html#method.cancel),
+/// [`cancel_on_drop`](struct.GenStream.html#method.cancel_on_drop) or
+/// [`with_cancel`](struct.GenStream.html#method.with_cancel), the generator is resumed and, on reaching the block,
+/// runs its body and returns the body's value, so in a `GenTryStream` the body has to end with a `Result`.
+/// The body may use `gen_await!` and friends; items yielded meanwhile are discarded.
+/// Works with generators of every backend, in `co` bodies `.await` as usual.
+///
+#[cfg_attr(feature = "nightly", doc = " ```rust")]
+#[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+/// #![feature(coroutines)]
+///
+/// use {
+///     core::pin::Pin,
+///     futures::{executor::block_on, prelude::*, task::Poll},
+///     gen_stream::{gen_await, gen_on_cancel, GenTryStream},
+///     std::{cell::RefCell, rc::Rc},
+/// };
+///
+/// let flushed = Rc::new(RefCell::new(Vec::new()));
+/// let out = flushed.clone();
+///
+/// let mut writer = GenTryStream::from(Box::pin(#[coroutine] static move || {
+///     let mut pending = Vec::new();
+///     for i in 0..10 {
+///         pending.push(i);
+///         yield Poll::Ready(i);
+///         gen_on_cancel! {
+///             gen_await!(future::ready(()));
+///             out.borrow_mut().extend(pending.drain(..));
+///             Ok::<_, ()>(())
+///         }
+///     }
+///     Ok(())
+/// }));
+///
+/// block_on(async {
+///     assert_eq!(writer.next().await, Some(Ok(0)));
+///     assert_eq!(writer.next().await, Some(Ok(1)));
+///     assert_eq!(Pin::new(&mut writer).cancel(8).await, Some(Ok(())));
+///     assert_eq!(writer.next().await, None);
+/// });
+/// assert_eq!(*flushed.borrow(), vec![0, 1]);
+/// ```
+#[cfg(any(feature = "std", feature = "tls"))]
+#[macro_export]
+macro_rules! gen_on_cancel {
+    ($($body:tt)*) => {
+        if $crate::__private::enter_cleanup() {
+            return { $($body)* };
+        }
+    };
+}
+
+/// Evaluates to whether the stream is being cancelled, as a `bool`.
+///
+/// Lets the generator wind down on its own terms when cancelled with
+/// [`with_cancel`](struct.GenStream.html#method.with_cancel) or [`cancel`](struct.GenStream.html#method.cancel),
+/// e.g. by skipping work that would only be thrown away. Works with generators of every backend.
+#[cfg(any(feature = "std", feature = "tls"))]
+#[macro_export]
+macro_rules! gen_cancelled {
+    () => {
+        $crate::__private::is_cancelled()
+    };
+}
+
+/// Declares bounds on the number of items the generator is going to yield from now on.
+///
+/// Takes the same `(usize, Option<usize>)` pair as `Stream::size_hint`. The enclosing GenStream reports
+/// the latest declared bounds from its `size_hint`, adjusted for every item yielded since.
+/// Works with generators of every backend.
+///
+#[cfg_attr(feature = "nightly", doc = " ```rust")]
+#[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{prelude::*, task::Poll},
+///     gen_stream::{gen_size_hint, GenStream},
+/// };
+///
+/// let mut stream = GenStream::from(Box::pin(#[coroutine] static move || {
+///     let rows = vec![1, 2, 3];
+///     gen_size_hint!(rows.len(), Some(rows.len()));
+///     for row in rows {
+///         yield Poll::Ready(row);
+///     }
+/// }));
+///
+/// assert_eq!(stream.size_hint(), (0, None));
+/// assert_eq!(futures::executor::block_on(stream.next()), Some(1));
+/// assert_eq!(stream.size_hint(), (2, Some(2)));
+/// ```
+///
+/// Bounds declared by the generators and futures a generator awaits only apply to them:
+///
+#[cfg_attr(feature = "nightly", doc = " ```rust")]
+#[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+/// #![feature(coroutines)]
+///
+/// use {
+///     futures::{prelude::*, task::Poll},
+///     gen_stream::{gen_await, gen_size_hint, GenFuture, GenStream},
+/// };
+///
+/// let mut stream = GenStream::from(Box::pin(#[coroutine] static move || {
+///     let len = gen_await!(GenFuture::from(#[coroutine] || {
+///         gen_size_hint!(100, Some(100));
+///         if false {
+///             yield Poll::Ready(());
+///         }
+///         2
+///     }));
+///     for i in 0..len {
+///         yield Poll::Ready(i);
+///     }
+/// }));
+///
+/// assert_eq!(futures::executor::block_on(stream.next()), Some(0));
+/// assert_eq!(stream.size_hint(), (0, None));
+/// ```
+#[cfg(any(feature = "std", feature = "tls"))]
+#[macro_export]
+macro_rules! gen_size_hint {
+    ($lo:expr, $hi:expr) => {
+        $crate::__private::set_size_hint(($lo, $hi))
+    };
+}
+
+/// Bounds on the number of remaining items, as in `Stream::size_hint`.
+type SizeHint = (usize, Option<usize>);
+
+/// Without thread-local storage there is no way to declare a size hint.
+#[cfg(not(any(feature = "std", feature = "tls")))]
+fn with_size_hint<F, R>(f: F) -> (R, Option<SizeHint>)
+where
+    F: FnOnce() -> R,
+{
+    (f(), None)
+}
+
+/// Applies the size hint declared during a resume and accounts for the yielded item.
+fn update_size_hint<Y, R>(
+    hint: &mut SizeHint,
+    declared: Option<SizeHint>,
+    state: &GenState<Poll<Y>, R>,
+) {
+    if let Some(declared) = declared {
+        *hint = declared;
+    }
+
+    match state {
+        GenState::Yielded(Poll::Ready(_)) => {
+            *hint = (
+                hint.0.saturating_sub(1),
+                hint.1.map(|hi| hi.saturating_sub(1)),
+            )
+        }
+        GenState::Yielded(Poll::Pending) => {}
+        GenState::Complete(_) => *hint = (0, Some(0)),
+    }
+}
+
+/// Cooperative budget of consecutive ready items.
+#[derive(Clone, Copy, Debug, Default)]
+struct Budget {
+    limit: Option<usize>,
+    spent: usize,
+}
+
+impl Budget {
+    fn new(limit: usize) -> Self {
+        assert!(limit > 0, "budget must be nonzero");
+
+        Self {
+            limit: Some(limit),
+            spent: 0,
+        }
+    }
+
+    /// Refills the spent budget and wakes the task, returning `true` if the stream has to yield now.
+    fn poll_exhausted(&mut self, cx: &mut Context<'_>) -> bool {
+        match self.limit {
+            Some(limit) if self.spent >= limit => {
+                self.spent = 0;
+                cx.waker().wake_by_ref();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, ready: bool) {
+        if ready {
+            self.spent += 1;
+        } else {
+            self.spent = 0;
+        }
+    }
+}
+
+/// The result of resuming a generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GenState<Y, R> {
+    /// The generator suspended with a value.
+    Yielded(Y),
+    /// The generator completed with a return value.
+    Complete(R),
+}
+
+/// Generator-like state machine that can be driven by GenStreams.
+///
+/// Implemented for nightly coroutines and for [`co::Gen`](co/struct.Gen.html).
+pub trait Resume {
+    /// The type of value this generator yields.
+    type Yield;
+    /// The type of value this generator returns.
+    type Return;
+
+    /// Resumes the generator with the current task context.
+    fn poll_resume(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Self::Yield, Self::Return>;
+}
+
+#[cfg(all(feature = "nightly", any(feature = "std", feature = "tls")))]
+impl<G: Coroutine> Resume for G {
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn poll_resume(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Self::Yield, Self::Return> {
+        set_task_context(cx, || match self.resume(()) {
+            CoroutineState::Yielded(v) => GenState::Yielded(v),
+            CoroutineState::Complete(v) => GenState::Complete(v),
+        })
+    }
+}
+
+pin_project! {
+    /// Simple generator-based stream.
+    ///
+    /// Once the generator completes, the stream keeps returning `None` without resuming it again.
+    /// All GenStreams implement `FusedStream`, so they can be used in `select!` loops directly.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, stream::FusedStream, task::Poll},
+    ///     gen_stream::GenStream,
+    /// };
+    ///
+    /// let mut stream = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     yield Poll::Ready(1);
+    /// }));
+    ///
+    /// block_on(async {
+    ///     assert_eq!(stream.next().await, Some(1));
+    ///     assert!(!stream.is_terminated());
+    ///     assert_eq!(stream.next().await, None);
+    ///     assert!(stream.is_terminated());
+    ///     assert_eq!(stream.next().await, None);
+    /// });
+    /// ```
+    ///
+    /// A GenStream is `Unpin` exactly when its generator is. A `static` generator has to be pinned, either on
+    /// its own as above or together with the stream, and the stream cannot be polled unpinned:
+    ///
+    /// ```rust,compile_fail,E0277
+    /// #![feature(coroutines)]
+    ///
+    /// use {futures::{executor::block_on, prelude::*, task::Poll}, gen_stream::GenStream};
+    ///
+    /// let mut stream = GenStream::from(#[coroutine] static move || {
+    ///     let v = 1;
+    ///     let r = &v;
+    ///     yield Poll::Ready(*r);
+    /// });
+    ///
+    /// block_on(stream.next());
+    /// ```
+    ///
+    /// Once pinned and polled, it cannot be moved out of the pin:
+    ///
+    /// ```rust,compile_fail,E0277
+    /// #![feature(coroutines)]
+    ///
+    /// use {core::pin::Pin, futures::{executor::block_on, prelude::*, task::Poll}, gen_stream::GenStream};
+    ///
+    /// let mut stream = Box::pin(GenStream::from(#[coroutine] static move || {
+    ///     let v = 1;
+    ///     let r = &v;
+    ///     yield Poll::Ready(*r);
+    /// }));
+    ///
+    /// block_on(stream.next());
+    /// let moved = *Pin::into_inner(stream);
+    /// ```
+    pub struct GenStream<G> {
+        #[pin]
+        inner: G,
+        finished: bool,
+        hint: SizeHint,
+        budget: Budget,
+    }
 }
 
 impl<G> GenStream<G> {
-    unsafe_pinned!(inner: G);
+    /// Makes the stream yield to the executor after `budget` consecutive ready items.
+    ///
+    /// Once the budget is spent, the next poll wakes the task and returns `Poll::Pending` instead of
+    /// resuming the generator, so that a generator that is always ready cannot starve other tasks.
+    /// The budget is refilled whenever the stream returns `Poll::Pending`, not at the start of every task poll:
+    /// the stream cannot tell task polls apart, so ready items count towards the budget even if the consumer
+    /// returned to the executor in between.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::GenStream,
+    /// };
+    ///
+    /// let mut numbers = GenStream::from(Box::pin(#[coroutine] static move || {
+    ///     for i in 0..5 {
+    ///         yield Poll::Ready(i);
+    ///     }
+    /// }))
+    /// .with_budget(2);
+    ///
+    /// assert_eq!(numbers.next().now_or_never(), Some(Some(0)));
+    /// assert_eq!(numbers.next().now_or_never(), Some(Some(1)));
+    /// assert_eq!(numbers.next().now_or_never(), None);
+    /// assert_eq!(block_on(numbers.collect::<Vec<_>>()), vec![2, 3, 4]);
+    /// ```
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
 }
 
 impl<G> From<G> for GenStream<G> {
     fn from(inner: G) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            finished: false,
+            hint: (0, None),
+            budget: Budget::default(),
+        }
     }
 }
 
 impl<G, Y> Stream for GenStream<G>
 where
-    G: Generator<Yield = Poll<Y>, Return = ()>,
+    G: Resume<Yield = Poll<Y>, Return = ()>,
 {
     type Item = Y;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        set_task_context(cx, || match self.inner().resume() {
-            GeneratorState::Yielded(v) => v.map(Some),
-            GeneratorState::Complete(_) => Poll::Ready(None),
-        })
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        let inner = this.inner;
+        let (state, declared) = with_size_hint(|| inner.poll_resume(cx));
+        update_size_hint(this.hint, declared, &state);
+
+        match state {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(Some)
+            }
+            GenState::Complete(_) => {
+                *this.finished = true;
+
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hint
     }
 }
 
-impl<G: Unpin> Unpin for GenStream<G> {}
+impl<G, Y> stream::FusedStream for GenStream<G>
+where
+    G: Resume<Yield = Poll<Y>, Return = ()>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
 
-/// Stream based on generator that never ends.
-pub struct GenPerpetualStream<G> {
-    inner: G,
+pin_project! {
+    /// Stream based on generator that never ends.
+    pub struct GenPerpetualStream<G> {
+        #[pin]
+        inner: G,
+        budget: Budget,
+    }
 }
 
 impl<G> GenPerpetualStream<G> {
-    unsafe_pinned!(inner: G);
+    /// Makes the stream yield to the executor after `budget` consecutive ready items.
+    ///
+    /// See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
 }
 
 impl<G> From<G> for GenPerpetualStream<G> {
     fn from(inner: G) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            budget: Budget::default(),
+        }
     }
 }
 
+/// The generator must return `!` or any other type convertible into `Infallible`.
 impl<G, Y> Stream for GenPerpetualStream<G>
 where
-    G: Generator<Yield = Poll<Y>, Return = !>,
+    G: Resume<Yield = Poll<Y>>,
+    G::Return: Into<Infallible>,
 {
     type Item = Y;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        set_task_context(cx, || match self.inner().resume() {
-            GeneratorState::Yielded(v) => v.map(Some),
-            GeneratorState::Complete(_) => unreachable!(),
-        })
+        let this = self.project();
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        match this.inner.poll_resume(cx) {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(Some)
+            }
+            GenState::Complete(_) => unreachable!(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
     }
 }
 
-impl<G: Unpin> Unpin for GenPerpetualStream<G> {}
+impl<G, Y> stream::FusedStream for GenPerpetualStream<G>
+where
+    G: Resume<Yield = Poll<Y>>,
+    G::Return: Into<Infallible>,
+{
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
 
-/// Stream based on generator that may fail.
-pub struct GenTryStream<G> {
-    inner: G,
-    finished: bool,
+pin_project! {
+    /// Stream based on generator that may fail.
+    ///
+    /// The first `Err` returned by the generator is yielded as the last item of the stream.
+    /// Pinning works as for [`GenStream`](struct.GenStream.html):
+    ///
+    /// ```rust,compile_fail,E0277
+    /// #![feature(coroutines)]
+    ///
+    /// use {core::pin::Pin, futures::{executor::block_on, prelude::*, task::Poll}, gen_stream::GenTryStream};
+    ///
+    /// let mut stream = Box::pin(GenTryStream::from(#[coroutine] static move || {
+    ///     let v = 1;
+    ///     let r = &v;
+    ///     yield Poll::Ready(*r);
+    ///     Err("done")
+    /// }));
+    ///
+    /// block_on(stream.next());
+    /// let moved = *Pin::into_inner(stream);
+    /// ```
+    pub struct GenTryStream<G> {
+        #[pin]
+        inner: G,
+        finished: bool,
+        hint: SizeHint,
+        budget: Budget,
+    }
 }
 
 impl<G> GenTryStream<G> {
-    unsafe_pinned!(inner: G);
-    unsafe_pinned!(finished: bool);
+    /// Wraps the generator, keeping the state of the stream.
+    #[cfg(feature = "std")]
+    fn map_inner<H>(self, f: impl FnOnce(G) -> H) -> GenTryStream<H> {
+        GenTryStream {
+            inner: f(self.inner),
+            finished: self.finished,
+            hint: self.hint,
+            budget: self.budget,
+        }
+    }
+
+    /// Makes the stream yield to the executor after `budget` consecutive ready items.
+    ///
+    /// See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
 }
 
 impl<G> From<G> for GenTryStream<G> {
@@ -173,25 +1408,39 @@ impl<G> From<G> for GenTryStream<G> {
         Self {
             inner,
             finished: false,
+            hint: (0, None),
+            budget: Budget::default(),
         }
     }
 }
 
 impl<G, T, E> Stream for GenTryStream<G>
 where
-    G: Generator<Yield = Poll<T>, Return = Result<(), E>>,
+    G: Resume<Yield = Poll<T>, Return = Result<(), E>>,
 {
     type Item = Result<T, E>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.finished {
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
             return Poll::Ready(None);
         }
 
-        set_task_context(cx, || match self.as_mut().inner().resume() {
-            GeneratorState::Yielded(v) => v.map(Ok).map(Some),
-            GeneratorState::Complete(res) => {
-                self.as_mut().finished().set(true);
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        let inner = this.inner;
+        let (state, declared) = with_size_hint(|| inner.poll_resume(cx));
+        update_size_hint(this.hint, declared, &state);
+
+        match state {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(Ok).map(Some)
+            }
+            GenState::Complete(res) => {
+                *this.finished = true;
 
                 if let Err(e) = res {
                     Poll::Ready(Some(Err(e)))
@@ -199,8 +1448,308 @@ where
                     Poll::Ready(None)
                 }
             }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hint
+    }
+}
+
+impl<G, T, E> stream::FusedStream for GenTryStream<G>
+where
+    G: Resume<Yield = Poll<T>, Return = Result<(), E>>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
+
+/// Error of a [`GenRecoverableStream`](struct.GenRecoverableStream.html).
+///
+/// Displays the kind of error only, the error itself is its `source`.
+///
+#[cfg_attr(feature = "std", doc = " ```rust")]
+#[cfg_attr(not(feature = "std"), doc = " ```ignore")]
+/// use {gen_stream::StreamError, std::error::Error};
+///
+/// let e = StreamError::<_, std::io::Error>::Recoverable("x".parse::<u8>().unwrap_err());
+/// assert_eq!(e.to_string(), "recoverable stream error");
+/// assert_eq!(e.source().unwrap().to_string(), "invalid digit found in string");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StreamError<E, F> {
+    /// Error yielded by the generator, the stream goes on.
+    Recoverable(E),
+    /// Error returned by the generator, the stream has ended.
+    Fatal(F),
+}
+
+impl<E, F> fmt::Display for StreamError<E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StreamError::Recoverable(_) => "recoverable stream error",
+            StreamError::Fatal(_) => "fatal stream error",
         })
     }
 }
 
-impl<G: Unpin> Unpin for GenTryStream<G> {}
+#[cfg(feature = "std")]
+impl<E, F> std::error::Error for StreamError<E, F>
+where
+    E: std::error::Error + 'static,
+    F: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Recoverable(e) => Some(e),
+            StreamError::Fatal(e) => Some(e),
+        }
+    }
+}
+
+pin_project! {
+    /// Stream based on generator that may fail, both recoverably and fatally.
+    ///
+    /// The generator yields `Poll<Result<T, E>>`, where `Err(e)` becomes `StreamError::Recoverable(e)` and
+    /// the stream continues. A returned `Err(f)` becomes `StreamError::Fatal(f)` and ends the stream, just like in `GenTryStream`.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{GenRecoverableStream, StreamError},
+    /// };
+    ///
+    /// let frames = GenRecoverableStream::from(Box::pin(#[coroutine] static move || {
+    ///     for frame in &["1", "x", "3", ""] {
+    ///         if frame.is_empty() {
+    ///             return Err("connection closed");
+    ///         }
+    ///         yield Poll::Ready(frame.parse::<u32>().map_err(|_| "bad frame"));
+    ///     }
+    ///     Ok(())
+    /// }));
+    ///
+    /// assert_eq!(
+    ///     block_on(frames.collect::<Vec<_>>()),
+    ///     vec![
+    ///         Ok(1),
+    ///         Err(StreamError::Recoverable("bad frame")),
+    ///         Ok(3),
+    ///         Err(StreamError::Fatal("connection closed")),
+    ///     ]
+    /// );
+    /// ```
+    pub struct GenRecoverableStream<G> {
+        #[pin]
+        inner: G,
+        finished: bool,
+        hint: SizeHint,
+        budget: Budget,
+    }
+}
+
+impl<G> GenRecoverableStream<G> {
+    /// Makes the stream yield to the executor after `budget` consecutive ready items.
+    ///
+    /// See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
+}
+
+impl<G> From<G> for GenRecoverableStream<G> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            finished: false,
+            hint: (0, None),
+            budget: Budget::default(),
+        }
+    }
+}
+
+impl<G, T, E, F> Stream for GenRecoverableStream<G>
+where
+    G: Resume<Yield = Poll<Result<T, E>>, Return = Result<(), F>>,
+{
+    type Item = Result<T, StreamError<E, F>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        let inner = this.inner;
+        let (state, declared) = with_size_hint(|| inner.poll_resume(cx));
+        update_size_hint(this.hint, declared, &state);
+
+        match state {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(|res| Some(res.map_err(StreamError::Recoverable)))
+            }
+            GenState::Complete(res) => {
+                *this.finished = true;
+
+                if let Err(e) = res {
+                    Poll::Ready(Some(Err(StreamError::Fatal(e))))
+                } else {
+                    Poll::Ready(None)
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hint
+    }
+}
+
+impl<G, T, E, F> stream::FusedStream for GenRecoverableStream<G>
+where
+    G: Resume<Yield = Poll<Result<T, E>>, Return = Result<(), F>>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
+
+pin_project! {
+    /// Stream based on generator with a return value.
+    ///
+    /// The stream ends once the generator completes, after which its return value can be taken with
+    /// [`take_return`](#method.take_return). Alternatively, [`into_return`](#method.into_return) turns the stream
+    /// into a future resolving to the return value.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     core::pin::Pin,
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::GenStreamWithReturn,
+    /// };
+    ///
+    /// let rows = || {
+    ///     GenStreamWithReturn::from(Box::pin(#[coroutine] static move || {
+    ///         let mut count = 0;
+    ///         for row in &["a", "b", "c"] {
+    ///             count += 1;
+    ///             yield Poll::Ready(*row);
+    ///         }
+    ///         count
+    ///     }))
+    /// };
+    ///
+    /// block_on(async {
+    ///     let mut stream = rows();
+    ///     assert_eq!(stream.by_ref().collect::<Vec<_>>().await, vec!["a", "b", "c"]);
+    ///     assert_eq!(Pin::new(&mut stream).take_return(), Some(3));
+    ///
+    ///     let mut stream = rows();
+    ///     assert_eq!(stream.next().await, Some("a"));
+    ///     assert_eq!(stream.into_return().await, 3);
+    /// });
+    /// ```
+    pub struct GenStreamWithReturn<G, R> {
+        #[pin]
+        inner: G,
+        output: Option<R>,
+        finished: bool,
+        hint: SizeHint,
+        budget: Budget,
+    }
+}
+
+impl<G, R> GenStreamWithReturn<G, R> {
+    /// Takes the return value of the generator, if the stream has ended and it has not been taken yet.
+    pub fn take_return(self: Pin<&mut Self>) -> Option<R> {
+        self.project().output.take()
+    }
+
+    /// Turns the stream into a future resolving to the return value of the generator.
+    ///
+    /// The remaining items are skipped. If the return value has already been taken, the future panics.
+    pub fn into_return(self) -> GenProgressFuture<G, R> {
+        GenProgressFuture::with_state(self.inner, self.output, self.finished, self.budget)
+    }
+
+    /// Makes the stream yield to the executor after `budget` consecutive ready items.
+    ///
+    /// See [`GenStream::with_budget`](struct.GenStream.html#method.with_budget).
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Budget::new(budget);
+        self
+    }
+}
+
+impl<G, R> From<G> for GenStreamWithReturn<G, R> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            output: None,
+            finished: false,
+            hint: (0, None),
+            budget: Budget::default(),
+        }
+    }
+}
+
+impl<G, Y, R> Stream for GenStreamWithReturn<G, R>
+where
+    G: Resume<Yield = Poll<Y>, Return = R>,
+{
+    type Item = Y;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+
+        if this.budget.poll_exhausted(cx) {
+            return Poll::Pending;
+        }
+
+        let inner = this.inner;
+        let (state, declared) = with_size_hint(|| inner.poll_resume(cx));
+        update_size_hint(this.hint, declared, &state);
+
+        match state {
+            GenState::Yielded(v) => {
+                this.budget.record(v.is_ready());
+                v.map(Some)
+            }
+            GenState::Complete(v) => {
+                *this.output = Some(v);
+                *this.finished = true;
+
+                Poll::Ready(None)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hint
+    }
+}
+
+impl<G, Y, R> stream::FusedStream for GenStreamWithReturn<G, R>
+where
+    G: Resume<Yield = Poll<Y>, Return = R>,
+{
+    fn is_terminated(&self) -> bool {
+        self.finished
+    }
+}
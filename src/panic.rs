@@ -0,0 +1,140 @@
+use {
+    crate::{GenState, GenTryStream, Resume},
+    core::{
+        any::Any,
+        fmt,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    pin_project_lite::pin_project,
+    std::{
+        panic::{self, AssertUnwindSafe},
+        sync::{Mutex, PoisonError},
+    },
+};
+
+/// Error reported by a [`GenTryStream`](struct.GenTryStream.html) in `catch_panics` mode when the generator panics.
+///
+/// The error is `Send` and `Sync`, so it converts into `Box<dyn Error + Send + Sync>`.
+pub struct PanicError {
+    message: Option<String>,
+    // Never locked, it only makes the payload `Sync`.
+    payload: Mutex<Box<dyn Any + Send>>,
+}
+
+impl PanicError {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(s) = payload.downcast_ref::<&'static str>() {
+            Some(s.to_string())
+        } else {
+            payload.downcast_ref::<String>().cloned()
+        };
+
+        Self {
+            message,
+            payload: Mutex::new(payload),
+        }
+    }
+
+    /// Returns the panic message, if the payload is a string.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Returns the panic payload, e.g. to resume unwinding with `std::panic::resume_unwind`.
+    pub fn into_payload(self) -> Box<dyn Any + Send> {
+        self.payload
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PanicError")
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "generator panicked: {}", message),
+            None => f.write_str("generator panicked"),
+        }
+    }
+}
+
+impl std::error::Error for PanicError {}
+
+pin_project! {
+    /// Adapter completing the generator with a `PanicError` when it panics.
+    ///
+    /// Created by [`GenTryStream::catch_panics`](struct.GenTryStream.html#method.catch_panics).
+    pub struct CatchPanics<G> {
+        #[pin]
+        inner: G,
+    }
+}
+
+impl<G, E> Resume for CatchPanics<G>
+where
+    G: Resume<Return = Result<(), E>>,
+    E: From<PanicError>,
+{
+    type Yield = G::Yield;
+    type Return = G::Return;
+
+    fn poll_resume(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Self::Yield, Self::Return> {
+        // The generator is never resumed after a panic, so its broken invariants cannot be observed.
+        match panic::catch_unwind(AssertUnwindSafe(|| self.project().inner.poll_resume(cx))) {
+            Ok(state) => state,
+            Err(payload) => GenState::Complete(Err(PanicError::new(payload).into())),
+        }
+    }
+}
+
+impl<G, T, E> GenTryStream<G>
+where
+    G: Resume<Yield = Poll<T>, Return = Result<(), E>>,
+    E: From<PanicError>,
+{
+    /// Turns panics of the generator into a final `Err` item.
+    ///
+    /// The panic is converted into the error type with `From<PanicError>` and ends the stream,
+    /// the generator is never resumed again.
+    ///
+    #[cfg_attr(feature = "nightly", doc = " ```rust")]
+    #[cfg_attr(not(feature = "nightly"), doc = " ```ignore")]
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{GenTryStream, PanicError},
+    /// };
+    ///
+    /// let plugin = GenTryStream::from(Box::pin(#[coroutine] static move || {
+    ///     yield Poll::Ready(1);
+    ///     panic!("plugin bug");
+    ///     #[allow(unreachable_code)]
+    ///     Ok::<_, PanicError>(())
+    /// }))
+    /// .catch_panics();
+    ///
+    /// # std::panic::set_hook(Box::new(|_| {}));
+    /// let items = block_on(plugin.collect::<Vec<_>>());
+    /// assert_eq!(items[0].as_ref().ok(), Some(&1));
+    /// assert_eq!(items[1].as_ref().unwrap_err().message(), Some("plugin bug"));
+    /// assert_eq!(items.len(), 2);
+    ///
+    /// let error: Box<dyn std::error::Error + Send + Sync> = items.into_iter().nth(1).unwrap().unwrap_err().into();
+    /// assert_eq!(error.to_string(), "generator panicked: plugin bug");
+    /// ```
+    pub fn catch_panics(self) -> GenTryStream<CatchPanics<G>> {
+        self.map_inner(|inner| CatchPanics { inner })
+    }
+}
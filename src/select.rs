@@ -0,0 +1,37 @@
+//! Branch target of `gen_select!`.
+
+use {
+    crate::yield_from::FromStream,
+    core::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::Stream,
+};
+
+/// Marker for selecting on a `Future`.
+pub enum FromFuture {}
+
+/// Something `gen_select!` can race: a future resolving to its output, or a stream resolving to its next item.
+pub trait Select<M> {
+    type Output;
+
+    fn poll_select(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output>;
+}
+
+impl<F: Future> Select<FromFuture> for F {
+    type Output = F::Output;
+
+    fn poll_select(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll(cx)
+    }
+}
+
+impl<S: Stream> Select<FromStream> for S {
+    type Output = Option<S::Item>;
+
+    fn poll_select(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_next(cx)
+    }
+}
@@ -0,0 +1,143 @@
+use {
+    crate::tls::set_task_context,
+    core::{
+        marker::PhantomData,
+        ops::{Coroutine, CoroutineState},
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_sink::Sink,
+    pin_project_lite::pin_project,
+};
+
+pin_project! {
+    /// Sink based on generator that consumes items.
+    ///
+    /// The generator receives `Some(item)` as its resume argument whenever it yields `Poll::Ready(())` to ask for
+    /// the next item, and `None` once the sink is being closed. `Poll::Pending` (as yielded by `gen_await!`) means
+    /// the generator is waiting, which applies back-pressure to the sender; the resume argument is always `None` then.
+    /// The first item is passed as the generator argument.
+    ///
+    /// Returning `Err(e)` fails the sink with `e`. If the generator returns before the sink is closed, further items are discarded.
+    ///
+    /// ```rust
+    /// #![feature(coroutines)]
+    ///
+    /// use {
+    ///     futures::{executor::block_on, prelude::*, task::Poll},
+    ///     gen_stream::{gen_await, GenSink},
+    ///     std::{cell::RefCell, rc::Rc},
+    /// };
+    ///
+    /// let written = Rc::new(RefCell::new(Vec::new()));
+    /// let log = written.clone();
+    ///
+    /// let mut sink = GenSink::from(Box::pin(#[coroutine] static move |mut item: Option<u32>| {
+    ///     while let Some(n) = item {
+    ///         if n == 0 {
+    ///             return Err("zero is not allowed");
+    ///         }
+    ///
+    ///         gen_await!(future::ready(()));
+    ///         log.borrow_mut().push(n);
+    ///
+    ///         item = yield Poll::Ready(());
+    ///     }
+    ///     log.borrow_mut().push(u32::MAX);
+    ///     Ok(())
+    /// }));
+    ///
+    /// block_on(async {
+    ///     sink.send(1).await.unwrap();
+    ///     sink.send(2).await.unwrap();
+    ///     sink.close().await.unwrap();
+    /// });
+    /// assert_eq!(*written.borrow(), vec![1, 2, u32::MAX]);
+    /// ```
+    pub struct GenSink<G, T> {
+        #[pin]
+        inner: G,
+        item: Option<T>,
+        ready: bool,
+        finished: bool,
+        _marker: PhantomData<fn(T)>,
+    }
+}
+
+impl<G, T> From<G> for GenSink<G, T> {
+    fn from(inner: G) -> Self {
+        Self {
+            inner,
+            item: None,
+            ready: true,
+            finished: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<G, T, E> GenSink<G, T>
+where
+    G: Coroutine<Option<T>, Yield = Poll<()>, Return = Result<(), E>>,
+{
+    /// Drives the generator until it has consumed the buffered item and asks for the next one.
+    /// When closing, keeps resuming it with `None` until it completes.
+    fn poll_resume(self: Pin<&mut Self>, cx: &mut Context<'_>, close: bool) -> Poll<Result<(), E>> {
+        let mut this = self.project();
+        loop {
+            if *this.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            let arg = if *this.ready {
+                match this.item.take() {
+                    Some(item) => Some(item),
+                    None if close => None,
+                    None => return Poll::Ready(Ok(())),
+                }
+            } else {
+                None
+            };
+            *this.ready = false;
+
+            match set_task_context(cx, || this.inner.as_mut().resume(arg)) {
+                CoroutineState::Yielded(Poll::Ready(())) => *this.ready = true,
+                CoroutineState::Yielded(Poll::Pending) => return Poll::Pending,
+                CoroutineState::Complete(res) => {
+                    *this.finished = true;
+                    *this.item = None;
+
+                    return Poll::Ready(res);
+                }
+            }
+        }
+    }
+}
+
+impl<G, T, E> Sink<T> for GenSink<G, T>
+where
+    G: Coroutine<Option<T>, Yield = Poll<()>, Return = Result<(), E>>,
+{
+    type Error = E;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_resume(cx, false)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.project();
+        if !*this.finished {
+            *this.item = Some(item);
+        }
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_resume(cx, false)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_resume(cx, true)
+    }
+}
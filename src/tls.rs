@@ -0,0 +1,233 @@
+//! Thread-local plumbing between the wrappers and the macros used inside generators.
+//!
+//! Generators resumed with `()` have no way to receive the task context, so the wrappers stash it in
+//! a thread-local slot for the duration of `resume` and `gen_await!` picks it up from there.
+//! Size hints declared with `gen_size_hint!` travel the other way: every resume gets a fresh slot for them,
+//! so that generators resumed by the generator itself cannot overwrite its hint, and hands the declared hint
+//! on through another slot when it is over. Cancellation is requested through a slot of its own and moved into
+//! another one by the resume it applies to, so that streams polled by the cancelled generator itself are not
+//! affected. When the resume is over, its outcome is handed back through the request slot.
+//! Without `std` the slots are `#[thread_local]` statics.
+
+#[cfg(feature = "nightly")]
+use core::{
+    future::Future,
+    pin::Pin,
+    ptr::NonNull,
+    task::{Context, Poll},
+};
+use {crate::SizeHint, core::cell::Cell};
+
+/// Defines a thread-local slot together with a function replacing its value.
+macro_rules! slot {
+    ($(#[$attr:meta])* static $name:ident: $ty:ty; fn $replace:ident;) => {
+        $(#[$attr])*
+        #[cfg(feature = "std")]
+        std::thread_local!(static $name: Cell<Option<$ty>> = const { Cell::new(None) });
+
+        $(#[$attr])*
+        #[cfg(not(feature = "std"))]
+        #[thread_local]
+        static $name: Cell<Option<$ty>> = Cell::new(None);
+
+        $(#[$attr])*
+        fn $replace(value: Option<$ty>) -> Option<$ty> {
+            #[cfg(feature = "std")]
+            return $name.with(|slot| slot.replace(value));
+
+            #[cfg(not(feature = "std"))]
+            return $name.replace(value);
+        }
+    };
+}
+
+slot! {
+    #[cfg(feature = "nightly")]
+    static TASK_CONTEXT: NonNull<Context<'static>>;
+    fn replace_task_context;
+}
+
+slot! {
+    static SIZE_HINT: SizeHint;
+    fn replace_size_hint;
+}
+
+slot! {
+    static DECLARED_HINT: SizeHint;
+    fn replace_declared_hint;
+}
+
+slot! {
+    static CANCEL_REQUEST: Cancellation;
+    fn replace_cancel_request;
+}
+
+slot! {
+    static CANCELLED: Cancellation;
+    fn replace_cancelled;
+}
+
+/// How far the cancellation of a generator has gone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Cancellation {
+    /// `gen_on_cancel!` blocks complete the generator, awaits run as usual.
+    Cleanup,
+    /// Pending awaits are abandoned as well, until a `gen_on_cancel!` block is entered.
+    Interrupt,
+    /// An await was abandoned, the generator must not be resumed again.
+    Abandoned,
+}
+
+/// Restores the previous slot value on drop, so that the slot is left intact even on panic.
+struct Reset<T> {
+    replace: fn(Option<T>) -> Option<T>,
+    prev: Option<T>,
+}
+
+impl<T> Drop for Reset<T> {
+    fn drop(&mut self) {
+        (self.replace)(self.prev.take());
+    }
+}
+
+/// Makes the task context available to `gen_await!` while `f` runs.
+#[cfg(feature = "nightly")]
+pub(crate) fn set_task_context<F, R>(cx: &mut Context<'_>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let cx = NonNull::from(cx).cast::<Context<'static>>();
+    let _reset = Reset {
+        replace: replace_task_context,
+        prev: replace_task_context(Some(cx)),
+    };
+
+    enter_resume(f)
+}
+
+/// Calls `f` with the task context set by the enclosing wrapper.
+///
+/// # Panics
+///
+/// Panics if called outside of a generator resumed by one of GenStreams.
+#[cfg(feature = "nightly")]
+pub fn with_task_context<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Context<'_>) -> R,
+{
+    // Take the context out of the slot so that a nested call cannot alias it.
+    let mut cx = replace_task_context(None).expect("gen_await! used outside of a GenStream");
+    let _reset = Reset {
+        replace: replace_task_context,
+        prev: Some(cx),
+    };
+
+    // Safety: the pointer was set by `set_task_context`, whose caller keeps the context borrowed
+    // until the generator is suspended again.
+    f(unsafe { cx.as_mut() })
+}
+
+/// Polls a future with the task context set by the enclosing wrapper.
+///
+/// # Panics
+///
+/// Panics if called outside of a generator resumed by one of GenStreams.
+#[cfg(feature = "nightly")]
+pub fn poll_with_task_context<F>(f: Pin<&mut F>) -> Poll<F::Output>
+where
+    F: Future,
+{
+    with_task_context(|cx| f.poll(cx))
+}
+
+/// Runs the resume `f`, returning the size hint it declared with `gen_size_hint!`, if any.
+pub(crate) fn with_size_hint<F, R>(f: F) -> (R, Option<SizeHint>)
+where
+    F: FnOnce() -> R,
+{
+    let _reset = Reset {
+        replace: replace_declared_hint,
+        prev: replace_declared_hint(None),
+    };
+    let res = f();
+    let declared = replace_declared_hint(None);
+
+    (res, declared)
+}
+
+/// Declares the size hint of the remaining items to the enclosing wrapper.
+pub fn set_size_hint(hint: SizeHint) {
+    replace_size_hint(Some(hint));
+}
+
+/// Requests the next resume performed by `f` to cancel the generator, returning how far it went.
+pub(crate) fn with_cancellation<F, R>(mode: Cancellation, f: F) -> (R, Cancellation)
+where
+    F: FnOnce() -> R,
+{
+    let _reset = Reset {
+        replace: replace_cancel_request,
+        prev: replace_cancel_request(Some(mode)),
+    };
+    let res = f();
+    let outcome = replace_cancel_request(None).unwrap_or(mode);
+
+    (res, outcome)
+}
+
+/// Resumes a generator with `f`, applying a pending cancellation request to it and isolating its size hint.
+#[cfg(any(feature = "nightly", feature = "co"))]
+pub(crate) fn enter_resume<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let _hint = Reset {
+        replace: replace_size_hint,
+        prev: replace_size_hint(None),
+    };
+    let mut request = Reset {
+        replace: replace_cancel_request,
+        prev: replace_cancel_request(None),
+    };
+    let _cancelled = Reset {
+        replace: replace_cancelled,
+        prev: replace_cancelled(request.prev),
+    };
+    let res = f();
+    // Hand the outcome back to `with_cancellation` and the hint to `with_size_hint`.
+    request.prev = replace_cancelled(None);
+    replace_declared_hint(replace_size_hint(None));
+
+    res
+}
+
+/// Updates the cancellation of the running generator, if any.
+fn update_cancelled(f: fn(Cancellation) -> Cancellation) -> Option<Cancellation> {
+    let cancelled = replace_cancelled(None).map(f);
+    replace_cancelled(cancelled);
+
+    cancelled
+}
+
+/// Whether the enclosing wrapper is cancelling the generator.
+pub fn is_cancelled() -> bool {
+    update_cancelled(|c| c).is_some()
+}
+
+/// Whether the generator is cancelled, in which case its awaits are no longer abandoned.
+pub fn enter_cleanup() -> bool {
+    update_cancelled(|_| Cancellation::Cleanup).is_some()
+}
+
+/// Whether the generator is being interrupted, in which case a pending `gen_await_next!` ends its stream.
+pub fn interrupt_next() -> bool {
+    update_cancelled(|c| c) == Some(Cancellation::Interrupt)
+}
+
+/// Abandons the pending await if the generator is being interrupted.
+pub fn interrupt_await() {
+    update_cancelled(|c| match c {
+        Cancellation::Interrupt => Cancellation::Abandoned,
+        c => c,
+    });
+}
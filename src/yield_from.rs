@@ -0,0 +1,61 @@
+//! Delegation target of `gen_yield_from!`.
+//!
+//! Streams and generators both implement [`YieldFrom`](trait.YieldFrom.html); the marker parameter keeps
+//! the two blanket impls apart and is inferred at the call site.
+
+use {
+    crate::{GenState, Resume},
+    core::{
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::Stream,
+};
+
+/// Marker for delegating to a `Stream`.
+pub enum FromStream {}
+
+/// Marker for delegating to a generator.
+pub enum FromGen {}
+
+/// Something `gen_yield_from!` can re-yield the items of.
+pub trait YieldFrom<M> {
+    type Item;
+    type Output;
+
+    fn poll_yield_from(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Poll<Self::Item>, Self::Output>;
+}
+
+impl<S: Stream> YieldFrom<FromStream> for S {
+    type Item = S::Item;
+    type Output = ();
+
+    fn poll_yield_from(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Poll<Self::Item>, Self::Output> {
+        match self.poll_next(cx) {
+            Poll::Ready(Some(v)) => GenState::Yielded(Poll::Ready(v)),
+            Poll::Ready(None) => GenState::Complete(()),
+            Poll::Pending => GenState::Yielded(Poll::Pending),
+        }
+    }
+}
+
+impl<G, T> YieldFrom<FromGen> for G
+where
+    G: Resume<Yield = Poll<T>>,
+{
+    type Item = T;
+    type Output = G::Return;
+
+    fn poll_yield_from(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> GenState<Poll<Self::Item>, Self::Output> {
+        self.poll_resume(cx)
+    }
+}